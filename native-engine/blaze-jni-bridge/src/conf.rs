@@ -44,6 +44,10 @@ define_conf!(IntConf, PARTIAL_AGG_SKIPPING_MIN_ROWS);
 define_conf!(BooleanConf, PARQUET_ENABLE_PAGE_FILTERING);
 define_conf!(BooleanConf, PARQUET_ENABLE_BLOOM_FILTER);
 define_conf!(StringConf, SPARK_IO_COMPRESSION_CODEC);
+define_conf!(StringConf, SHUFFLE_COMPRESSION_CODEC);
+define_conf!(IntConf, SHUFFLE_COMPRESSION_ZSTD_LEVEL);
+define_conf!(BooleanConf, IO_CHECKSUM_ENABLE);
+define_conf!(IntConf, IO_MAX_FRAME_SIZE);
 
 pub trait BooleanConf {
     fn key(&self) -> &'static str;
@@ -12,8 +12,11 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::collections::HashSet;
+
 use arrow::{
-    array::{Array, ArrayData},
+    array::{Array, ArrayData, ArrayRef},
+    datatypes::{ArrowNativeType, DataType},
     record_batch::RecordBatch,
 };
 
@@ -57,3 +60,213 @@ fn get_array_data_mem_size(array_data: &ArrayData) -> usize {
     }
     mem_size
 }
+
+/// Estimates the number of bytes actually referenced by the batch.
+///
+/// unlike get_array_mem_size(), only the sliced ranges of buffers are counted
+/// and dictionary values shared by multiple columns are counted once.
+pub fn batch_estimated_size(batch: &RecordBatch) -> usize {
+    batch_column_estimated_sizes(batch).into_iter().sum()
+}
+
+/// Same as batch_estimated_size(), but returns the size of each column.
+///
+/// shared dictionary values are counted in the first column referencing them.
+pub fn batch_column_estimated_sizes(batch: &RecordBatch) -> Vec<usize> {
+    column_estimated_sizes(batch.columns())
+}
+
+/// Same as batch_column_estimated_sizes(), but takes columns not wrapped in a
+/// RecordBatch.
+pub fn column_estimated_sizes(cols: &[ArrayRef]) -> Vec<usize> {
+    let mut seen_dict_values = HashSet::new();
+    cols.iter()
+        .map(|array| get_array_data_estimated_size(&array.to_data(), &mut seen_dict_values))
+        .collect()
+}
+
+fn get_array_data_estimated_size(
+    array_data: &ArrayData,
+    seen_dict_values: &mut HashSet<Vec<usize>>,
+) -> usize {
+    let offset = array_data.offset();
+    let len = array_data.len();
+    let mut mem_size = array_data
+        .nulls()
+        .map(|_| (len + 7) / 8)
+        .unwrap_or_default();
+
+    macro_rules! offsets_range {
+        ($offset_ty:ty) => {{
+            // empty arrays may come with an empty offsets buffer, e.g. from ffi
+            let offsets = array_data.buffer::<$offset_ty>(0);
+            if len == 0 || offsets.is_empty() {
+                return mem_size;
+            }
+            let offsets = &offsets[..len + 1];
+            let start = offsets[0].as_usize();
+            let end = offsets[len].as_usize();
+            (offsets.len() * size_of::<$offset_ty>(), start, end)
+        }};
+    }
+
+    match array_data.data_type() {
+        DataType::Null => {}
+        DataType::Boolean => mem_size += (len + 7) / 8,
+        DataType::FixedSizeBinary(width) => mem_size += len * *width as usize,
+        dt if dt.is_primitive() => mem_size += len * dt.primitive_width().unwrap_or_default(),
+        DataType::Utf8 | DataType::Binary => {
+            let (offsets_size, start, end) = offsets_range!(i32);
+            mem_size += offsets_size + (end - start);
+        }
+        DataType::LargeUtf8 | DataType::LargeBinary => {
+            let (offsets_size, start, end) = offsets_range!(i64);
+            mem_size += offsets_size + (end - start);
+        }
+        DataType::List(_) | DataType::Map(..) => {
+            let (offsets_size, start, end) = offsets_range!(i32);
+            let child = array_data.child_data()[0].slice(start, end - start);
+            mem_size += offsets_size + get_array_data_estimated_size(&child, seen_dict_values);
+        }
+        DataType::LargeList(_) => {
+            let (offsets_size, start, end) = offsets_range!(i64);
+            let child = array_data.child_data()[0].slice(start, end - start);
+            mem_size += offsets_size + get_array_data_estimated_size(&child, seen_dict_values);
+        }
+        DataType::FixedSizeList(_, list_size) => {
+            let list_size = *list_size as usize;
+            let child = array_data.child_data()[0].slice(offset * list_size, len * list_size);
+            mem_size += get_array_data_estimated_size(&child, seen_dict_values);
+        }
+        DataType::Struct(_) => {
+            for child in array_data.child_data() {
+                // children of sliced struct data are normally sliced already
+                let child = if child.len() == len {
+                    child.clone()
+                } else {
+                    child.slice(offset, len)
+                };
+                mem_size += get_array_data_estimated_size(&child, seen_dict_values);
+            }
+        }
+        DataType::Dictionary(key_type, _) => {
+            mem_size += len * key_type.primitive_width().unwrap_or_default();
+
+            // all values may be referenced by the keys, count them once
+            let values = &array_data.child_data()[0];
+            let values_id = values
+                .buffers()
+                .iter()
+                .map(|buffer| buffer.as_ptr() as usize)
+                .chain([values.offset(), values.len()])
+                .collect::<Vec<_>>();
+            if values.buffers().is_empty() || seen_dict_values.insert(values_id) {
+                mem_size += get_array_data_estimated_size(values, seen_dict_values);
+            }
+        }
+        _ => mem_size = get_array_data_mem_size(array_data),
+    }
+    mem_size
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use arrow::{
+        array::*,
+        buffer::{Buffer, OffsetBuffer},
+        datatypes::*,
+        record_batch::RecordBatch,
+    };
+
+    use crate::array_size::{batch_column_estimated_sizes, batch_estimated_size};
+
+    #[test]
+    fn test_estimated_size_of_sliced_batch() {
+        let strings: ArrayRef = Arc::new(StringArray::from_iter_values(
+            (0..1000).map(|i| format!("value-{i:08}")),
+        ));
+        let ints: ArrayRef = Arc::new(Int64Array::from_iter((0..1000).map(|i| {
+            if i % 3 == 0 {
+                None
+            } else {
+                Some(i)
+            }
+        })));
+        let batch = RecordBatch::try_from_iter(vec![("s", strings), ("i", ints)]).unwrap();
+        let sliced = batch.slice(100, 50);
+
+        // s: 51 offsets + 50 * 14 bytes, i: 50 values + validity
+        let expected = (51 * 4 + 50 * 14) + (50 * 8 + 7);
+        assert_eq!(batch_estimated_size(&sliced), expected);
+        assert!(sliced.get_array_memory_size() > 10 * expected);
+    }
+
+    #[test]
+    fn test_estimated_size_of_nested_arrays() {
+        let list: ArrayRef = Arc::new(ListArray::from_iter_primitive::<Int32Type, _, _>(
+            (0..100).map(|i| Some(vec![Some(i); 10])),
+        ));
+        let struct_: ArrayRef = Arc::new(StructArray::from(vec![(
+            Arc::new(Field::new("list", list.data_type().clone(), true)),
+            list.clone(),
+        )]));
+        let batch = RecordBatch::try_from_iter(vec![("list", list), ("struct", struct_)]).unwrap();
+
+        // 3 lists with 10 int32 items each
+        let sliced = batch.slice(10, 3);
+        let list_size = 4 * 4 + 30 * 4;
+        assert_eq!(
+            batch_column_estimated_sizes(&sliced),
+            vec![list_size, list_size]
+        );
+    }
+
+    #[test]
+    fn test_estimated_size_of_shared_dictionary() {
+        let values: ArrayRef = Arc::new(StringArray::from_iter_values(
+            (0..100).map(|i| format!("value-{i:08}")),
+        ));
+        let keys1 = Int32Array::from_iter_values((0..1000).map(|i| i % 100));
+        let keys2 = Int32Array::from_iter_values((0..1000).map(|i| (i + 1) % 100));
+        let dict1: ArrayRef = Arc::new(DictionaryArray::new(keys1, values.clone()));
+        let dict2: ArrayRef = Arc::new(DictionaryArray::new(keys2, values.clone()));
+        let batch = RecordBatch::try_from_iter(vec![("d1", dict1), ("d2", dict2)]).unwrap();
+
+        // values are counted once, in the first column
+        let values_size = 101 * 4 + 100 * 14;
+        assert_eq!(
+            batch_column_estimated_sizes(&batch),
+            vec![1000 * 4 + values_size, 1000 * 4]
+        );
+        assert_eq!(
+            batch_estimated_size(&batch.slice(0, 10)),
+            2 * 10 * 4 + values_size
+        );
+    }
+
+    #[test]
+    fn test_estimated_size_of_empty_offsets() {
+        // zero-length string array with an empty offsets buffer
+        let strings = ArrayData::builder(DataType::Utf8)
+            .len(0)
+            .add_buffer(Buffer::from_vec(Vec::<i32>::new()))
+            .add_buffer(Buffer::from_vec(Vec::<u8>::new()))
+            .build()
+            .unwrap();
+        let strings: ArrayRef = Arc::new(StringArray::from(strings));
+
+        // list<string> whose lists are all empty
+        let list: ArrayRef = Arc::new(ListArray::new(
+            Arc::new(Field::new("item", DataType::Utf8, true)),
+            OffsetBuffer::new(vec![0, 0, 0].into()),
+            strings.clone(),
+            None,
+        ));
+        let batch = RecordBatch::try_from_iter(vec![("strings", strings)]).unwrap();
+        assert_eq!(batch_column_estimated_sizes(&batch), vec![0]);
+        let batch = RecordBatch::try_from_iter(vec![("list", list)]).unwrap();
+        assert_eq!(batch_column_estimated_sizes(&batch), vec![3 * 4]);
+    }
+}
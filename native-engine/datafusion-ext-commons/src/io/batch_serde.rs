@@ -27,7 +27,7 @@ use datafusion::common::Result;
 use unchecked_index::unchecked_index;
 
 use crate::{
-    df_unimplemented_err,
+    df_execution_err, df_unimplemented_err,
     io::{read_bytes_slice, read_len, write_len},
 };
 
@@ -104,6 +104,15 @@ pub fn read_array<R: Read>(
             read_primitive_array::<_, paste::paste! {[<$ty Type>]}>(num_rows, input)?
         }};
     }
+    macro_rules! read_timestamp {
+        ($ty:ident, $tz:expr) => {{
+            Arc::new(
+                as_primitive_array::<paste::paste! {[<$ty Type>]}>(&read_primitive!($ty))
+                    .clone()
+                    .with_timezone_opt($tz.clone()),
+            )
+        }};
+    }
     Ok(match data_type {
         DataType::Null => Arc::new(NullArray::new(num_rows)),
         DataType::Boolean => read_boolean_array(num_rows, input)?,
@@ -124,10 +133,10 @@ pub fn read_array<R: Read>(
         ),
         DataType::Date32 => read_primitive!(Date32),
         DataType::Date64 => read_primitive!(Date64),
-        DataType::Timestamp(TimeUnit::Second, _) => read_primitive!(TimestampSecond),
-        DataType::Timestamp(TimeUnit::Millisecond, _) => read_primitive!(TimestampMillisecond),
-        DataType::Timestamp(TimeUnit::Microsecond, _) => read_primitive!(TimestampMicrosecond),
-        DataType::Timestamp(TimeUnit::Nanosecond, _) => read_primitive!(TimestampNanosecond),
+        DataType::Timestamp(TimeUnit::Second, tz) => read_timestamp!(TimestampSecond, tz),
+        DataType::Timestamp(TimeUnit::Millisecond, tz) => read_timestamp!(TimestampMillisecond, tz),
+        DataType::Timestamp(TimeUnit::Microsecond, tz) => read_timestamp!(TimestampMicrosecond, tz),
+        DataType::Timestamp(TimeUnit::Nanosecond, tz) => read_timestamp!(TimestampNanosecond, tz),
         DataType::Utf8 => read_bytes_array(num_rows, input, DataType::Utf8)?,
         DataType::Binary => read_bytes_array(num_rows, input, DataType::Binary)?,
         DataType::List(list_field) => read_list_array(num_rows, input, list_field)?,
@@ -145,18 +154,20 @@ fn write_bits_buffer<W: Write>(
     bits_len: usize,
     output: &mut W,
 ) -> Result<()> {
-    let mut out_buffer = vec![0u8; (bits_len + 7) / 8];
-    let in_ptr = buffer.as_ptr();
-    let out_ptr = out_buffer.as_mut_ptr();
-
-    for i in 0..bits_len {
-        unsafe {
-            if arrow::util::bit_util::get_bit_raw(in_ptr, bits_offset + i) {
-                arrow::util::bit_util::set_bit_raw(out_ptr, i);
-            }
+    // bit_slice() is zero-copy for byte-aligned offsets, otherwise it shifts the
+    // bits word by word
+    let num_bytes = (bits_len + 7) / 8;
+    let sliced = buffer.bit_slice(bits_offset, bits_len);
+    let bytes = &sliced.as_slice()[..num_bytes];
+
+    // clear unused bits in the last byte
+    match bits_len % 8 {
+        0 => output.write_all(bytes)?,
+        num_tail_bits => {
+            output.write_all(&bytes[..num_bytes - 1])?;
+            output.write_all(&[bytes[num_bytes - 1] & ((1u8 << num_tail_bits) - 1)])?;
         }
     }
-    output.write_all(&out_buffer)?;
     Ok(())
 }
 
@@ -165,6 +176,47 @@ fn read_bits_buffer<R: Read>(input: &mut R, bits_len: usize) -> Result<Buffer> {
     Ok(Buffer::from_vec(buf.into()))
 }
 
+// written before the data of each array
+const NULL_BUFFER_NONE: usize = 0; // no nulls, null buffer is omitted
+const NULL_BUFFER_SOME: usize = 1; // followed by null buffer
+const NULL_BUFFER_ALL: usize = 2; // all values are null, null buffer and values are omitted
+
+// returns true if all values are null and the values can be omitted
+fn write_null_buffer<W: Write>(array_data: &ArrayData, output: &mut W) -> Result<bool> {
+    match array_data.nulls() {
+        Some(null_buffer)
+            if !null_buffer.is_empty() && null_buffer.null_count() == null_buffer.len() =>
+        {
+            write_len(NULL_BUFFER_ALL, output)?;
+            Ok(true)
+        }
+        Some(null_buffer) => {
+            write_len(NULL_BUFFER_SOME, output)?;
+            write_bits_buffer(
+                null_buffer.buffer(),
+                null_buffer.offset(),
+                null_buffer.len(),
+                output,
+            )?;
+            Ok(false)
+        }
+        None => {
+            write_len(NULL_BUFFER_NONE, output)?;
+            Ok(false)
+        }
+    }
+}
+
+// returns the null buffer and whether all values are null
+fn read_null_buffer<R: Read>(input: &mut R, num_rows: usize) -> Result<(Option<Buffer>, bool)> {
+    Ok(match read_len(input)? {
+        NULL_BUFFER_NONE => (None, false),
+        NULL_BUFFER_SOME => (Some(read_bits_buffer(input, num_rows)?), false),
+        NULL_BUFFER_ALL => (Some(MutableBuffer::new_null(num_rows).into()), true),
+        other => df_execution_err!("invalid null buffer flag: {other}")?,
+    })
+}
+
 fn write_primitive_array<W: Write, PT: ArrowPrimitiveType>(
     array: &PrimitiveArray<PT>,
     output: &mut W,
@@ -172,18 +224,9 @@ fn write_primitive_array<W: Write, PT: ArrowPrimitiveType>(
     let offset = array.offset();
     let len = array.len();
     let array_data = array.to_data();
-    if let Some(null_buffer) = array_data.nulls() {
-        write_len(1, output)?;
-        write_bits_buffer(
-            null_buffer.buffer(),
-            null_buffer.offset(),
-            null_buffer.len(),
-            output,
-        )?;
-    } else {
-        write_len(0, output)?;
+    if !write_null_buffer(&array_data, output)? {
+        write_primitive_raw_array(&array_data.buffer::<PT::Native>(0)[offset..][..len], output)?;
     }
-    write_primitive_raw_array(&array_data.buffer::<PT::Native>(0)[offset..][..len], output)?;
     Ok(())
 }
 
@@ -191,16 +234,14 @@ fn read_primitive_array<R: Read, PT: ArrowPrimitiveType>(
     num_rows: usize,
     input: &mut R,
 ) -> Result<ArrayRef> {
-    let has_null_buffer = read_len(input)? == 1;
-    let null_buffer: Option<Buffer> = if has_null_buffer {
-        Some(read_bits_buffer(input, num_rows)?)
-    } else {
-        None
-    };
+    let (null_buffer, all_null) = read_null_buffer(input, num_rows)?;
 
     let data_buffers: Vec<Buffer> = {
-        let data_buffer =
-            Buffer::from_vec(read_primitive_raw_array::<PT::Native, R>(input, num_rows)?);
+        let data_buffer = if all_null {
+            MutableBuffer::from_len_zeroed(num_rows * size_of::<PT::Native>()).into()
+        } else {
+            Buffer::from_vec(read_primitive_raw_array::<PT::Native, R>(input, num_rows)?)
+        };
         vec![data_buffer]
     };
 
@@ -216,16 +257,8 @@ fn read_primitive_array<R: Read, PT: ArrowPrimitiveType>(
 }
 
 fn write_list_array<W: Write>(array: &ListArray, output: &mut W) -> Result<()> {
-    if let Some(null_buffer) = array.to_data().nulls() {
-        write_len(1, output)?;
-        write_bits_buffer(
-            null_buffer.buffer(),
-            null_buffer.offset(),
-            null_buffer.len(),
-            output,
-        )?;
-    } else {
-        write_len(0, output)?;
+    if write_null_buffer(&array.to_data(), output)? {
+        return Ok(());
     }
 
     let value_offsets = array.value_offsets();
@@ -246,12 +279,18 @@ fn read_list_array<R: Read>(
     input: &mut R,
     list_field: &FieldRef,
 ) -> Result<ArrayRef> {
-    let has_null_buffer = read_len(input)? == 1;
-    let null_buffer: Option<Buffer> = if has_null_buffer {
-        Some(read_bits_buffer(input, num_rows)?)
-    } else {
-        None
-    };
+    let (null_buffer, all_null) = read_null_buffer(input, num_rows)?;
+    if all_null {
+        let array_data = ArrayData::try_new(
+            DataType::List(list_field.clone()),
+            num_rows,
+            null_buffer,
+            0,
+            vec![MutableBuffer::from_len_zeroed((num_rows + 1) * 4).into()],
+            vec![new_empty_array(list_field.data_type()).into_data()],
+        )?;
+        return Ok(make_array(array_data));
+    }
 
     let mut cur_offset = 0;
     let mut offsets_buffer = MutableBuffer::new((num_rows + 1) * 4);
@@ -278,17 +317,8 @@ fn read_list_array<R: Read>(
 }
 
 fn write_map_array<W: Write>(array: &MapArray, output: &mut W) -> Result<()> {
-    let array_data = array.to_data();
-    if let Some(null_buffer) = array_data.nulls() {
-        write_len(1, output)?;
-        write_bits_buffer(
-            null_buffer.buffer(),
-            null_buffer.offset(),
-            null_buffer.len(),
-            output,
-        )?;
-    } else {
-        write_len(0, output)?;
+    if write_null_buffer(&array.to_data(), output)? {
+        return Ok(());
     }
 
     let first_offset = array.value_offsets().first().cloned().unwrap_or_default();
@@ -316,12 +346,18 @@ fn read_map_array<R: Read>(
     map_field: &FieldRef,
     is_sorted: bool,
 ) -> Result<ArrayRef> {
-    let has_null_buffer = read_len(input)? == 1;
-    let null_buffer: Option<Buffer> = if has_null_buffer {
-        Some(read_bits_buffer(input, num_rows)?)
-    } else {
-        None
-    };
+    let (null_buffer, all_null) = read_null_buffer(input, num_rows)?;
+    if all_null {
+        let array_data = ArrayData::try_new(
+            DataType::Map(map_field.clone(), is_sorted),
+            num_rows,
+            null_buffer,
+            0,
+            vec![MutableBuffer::from_len_zeroed((num_rows + 1) * 4).into()],
+            vec![new_empty_array(map_field.data_type()).into_data()],
+        )?;
+        return Ok(make_array(array_data));
+    }
 
     let mut cur_offset = 0;
     let mut offsets_buffer = MutableBuffer::new((num_rows + 1) * 4);
@@ -367,18 +403,8 @@ fn read_map_array<R: Read>(
 }
 
 fn write_struct_array<W: Write>(array: &StructArray, output: &mut W) -> Result<()> {
-    let array_data = array.to_data();
-    if let Some(null_buffer) = array_data.nulls() {
-        write_len(1, output)?;
-        write_bits_buffer(
-            null_buffer.buffer(),
-            null_buffer.offset(),
-            null_buffer.len(),
-            output,
-        )?;
-    } else {
-        write_len(0, output)?;
-    }
+    // children are always written even if all values are null
+    write_null_buffer(&array.to_data(), output)?;
     for column in array.columns() {
         write_array(&column, output)?;
     }
@@ -386,12 +412,7 @@ fn write_struct_array<W: Write>(array: &StructArray, output: &mut W) -> Result<(
 }
 
 fn read_struct_array<R: Read>(num_rows: usize, input: &mut R, fields: &Fields) -> Result<ArrayRef> {
-    let has_null_buffer = read_len(input)? == 1;
-    let null_buffer: Option<Buffer> = if has_null_buffer {
-        Some(read_bits_buffer(input, num_rows)?)
-    } else {
-        None
-    };
+    let (null_buffer, _all_null) = read_null_buffer(input, num_rows)?;
 
     let child_arrays: Vec<ArrayRef> = fields
         .iter()
@@ -411,36 +432,26 @@ fn read_struct_array<R: Read>(num_rows: usize, input: &mut R, fields: &Fields) -
 
 fn write_boolean_array<W: Write>(array: &BooleanArray, output: &mut W) -> Result<()> {
     let array_data = array.to_data();
-    if let Some(null_buffer) = array_data.nulls() {
-        write_len(1, output)?;
+    if !write_null_buffer(&array_data, output)? {
         write_bits_buffer(
-            null_buffer.buffer(),
-            null_buffer.offset(),
-            null_buffer.len(),
+            &array_data.buffers()[0],
+            array.offset(),
+            array.len(),
             output,
         )?;
-    } else {
-        write_len(0, output)?;
-    }
-    write_bits_buffer(
-        &array_data.buffers()[0],
-        array.offset(),
-        array.len(),
-        output,
-    )?;
+    }
     Ok(())
 }
 
 fn read_boolean_array<R: Read>(num_rows: usize, input: &mut R) -> Result<ArrayRef> {
-    let has_null_buffer = read_len(input)? == 1;
-    let null_buffer: Option<Buffer> = if has_null_buffer {
-        Some(read_bits_buffer(input, num_rows)?)
-    } else {
-        None
-    };
+    let (null_buffer, all_null) = read_null_buffer(input, num_rows)?;
 
     let data_buffers: Vec<Buffer> = {
-        let data_buffer = read_bits_buffer(input, num_rows)?;
+        let data_buffer = if all_null {
+            MutableBuffer::new_null(num_rows).into()
+        } else {
+            read_bits_buffer(input, num_rows)?
+        };
         vec![data_buffer]
     };
 
@@ -459,16 +470,8 @@ fn write_bytes_array<T: ByteArrayType<Offset = i32>, W: Write>(
     array: &GenericByteArray<T>,
     output: &mut W,
 ) -> Result<()> {
-    if let Some(null_buffer) = array.to_data().nulls() {
-        write_len(1, output)?;
-        write_bits_buffer(
-            null_buffer.buffer(),
-            null_buffer.offset(),
-            null_buffer.len(),
-            output,
-        )?;
-    } else {
-        write_len(0, output)?;
+    if write_null_buffer(&array.to_data(), output)? {
+        return Ok(());
     }
 
     // transform offsets to lengths for better compression
@@ -490,12 +493,21 @@ fn read_bytes_array<R: Read>(
     input: &mut R,
     data_type: DataType,
 ) -> Result<ArrayRef> {
-    let has_null_buffer = read_len(input)? == 1;
-    let null_buffer: Option<Buffer> = if has_null_buffer {
-        Some(read_bits_buffer(input, num_rows)?)
-    } else {
-        None
-    };
+    let (null_buffer, all_null) = read_null_buffer(input, num_rows)?;
+    if all_null {
+        let array_data = ArrayData::try_new(
+            data_type,
+            num_rows,
+            null_buffer,
+            0,
+            vec![
+                MutableBuffer::from_len_zeroed((num_rows + 1) * 4).into(),
+                Buffer::from_vec(Vec::<u8>::new()),
+            ],
+            vec![],
+        )?;
+        return Ok(make_array(array_data));
+    }
 
     let lens = read_primitive_raw_array::<i32, R>(input, num_rows)?;
     let mut cur_offset = 0;
@@ -575,12 +587,18 @@ fn transpose_raw_bytes(src: &[u8], dest: &mut [u8], num_items: usize, num_item_b
 mod test {
     use std::{io::Cursor, sync::Arc};
 
-    use arrow::{array::*, datatypes::*, record_batch::RecordBatch};
+    use arrow::{
+        array::*,
+        buffer::{Buffer, OffsetBuffer},
+        datatypes::*,
+        record_batch::RecordBatch,
+    };
     use datafusion::assert_batches_eq;
 
     use crate::io::{
         batch_serde::{
-            read_batch, read_primitive_raw_array, write_batch, write_primitive_raw_array,
+            read_array, read_batch, read_primitive_raw_array, write_array, write_batch,
+            write_bits_buffer, write_primitive_raw_array,
         },
         recover_named_batch,
     };
@@ -594,6 +612,24 @@ mod test {
         assert_eq!(out, src)
     }
 
+    #[test]
+    fn test_write_bits_buffer() {
+        let buffer = Buffer::from_iter((0..160).map(|i| i % 3 == 0 || i % 7 == 0));
+        for bits_offset in 0..16 {
+            for bits_len in 0..130 {
+                let mut expected = vec![0u8; (bits_len + 7) / 8];
+                for i in 0..bits_len {
+                    if arrow::util::bit_util::get_bit(buffer.as_slice(), bits_offset + i) {
+                        arrow::util::bit_util::set_bit(&mut expected, i);
+                    }
+                }
+                let mut buf = vec![];
+                write_bits_buffer(&buffer, bits_offset, bits_len, &mut buf).unwrap();
+                assert_eq!(buf, expected, "offset={bits_offset}, len={bits_len}");
+            }
+        }
+    }
+
     #[test]
     fn test_write_and_read_batch() {
         let array1: ArrayRef = Arc::new(StringArray::from_iter([
@@ -796,4 +832,167 @@ mod test {
             sliced
         );
     }
+
+    #[test]
+    fn test_write_and_read_batch_for_all_null() {
+        // all-null column is written as a single flag
+        let all_null: ArrayRef = Arc::new(StringArray::from(vec![None::<&str>; 10]));
+        let mut buf = vec![];
+        write_array(&all_null, &mut buf).unwrap();
+        assert_eq!(buf.len(), 1);
+        let decoded = read_array(&mut Cursor::new(&buf), &DataType::Utf8, 10).unwrap();
+        assert_eq!(&decoded, &all_null);
+
+        let mut map_builder = MapBuilder::new(None, StringBuilder::new(), Int32Builder::new());
+        for valid in [false, false, true, false] {
+            if valid {
+                map_builder.keys().append_value("k");
+                map_builder.values().append_value(1);
+            }
+            map_builder.append(valid).unwrap();
+        }
+        let batch = RecordBatch::try_from_iter_with_nullable(vec![
+            (
+                "bool",
+                Arc::new(BooleanArray::from(vec![None, None, Some(true), None])) as ArrayRef,
+                true,
+            ),
+            (
+                "int",
+                Arc::new(Int32Array::from(vec![None, None, Some(1), None])),
+                true,
+            ),
+            (
+                "str",
+                Arc::new(StringArray::from(vec![None, None, Some("x"), None])),
+                true,
+            ),
+            (
+                "list",
+                Arc::new(ListArray::from_iter_primitive::<Int32Type, _, _>(vec![
+                    None,
+                    None,
+                    Some(vec![Some(1), None]),
+                    None,
+                ])),
+                true,
+            ),
+            ("map", Arc::new(map_builder.finish()), true),
+        ])
+        .unwrap();
+
+        // all-null slices are written as num_rows and one flag per column
+        for (offset, len, expected_size) in [(0, 2, Some(6)), (3, 1, Some(6)), (0, 4, None)] {
+            let sliced = batch.slice(offset, len);
+            let mut buf = vec![];
+            write_batch(sliced.num_rows(), sliced.columns(), &mut buf).unwrap();
+            if let Some(expected_size) = expected_size {
+                assert_eq!(buf.len(), expected_size);
+            }
+            let mut cursor = Cursor::new(buf);
+            let (decoded_num_rows, decoded_cols) =
+                read_batch(&mut cursor, &batch.schema()).unwrap();
+            assert_eq!(
+                recover_named_batch(decoded_num_rows, &decoded_cols, batch.schema()).unwrap(),
+                sliced
+            );
+        }
+    }
+
+    #[test]
+    fn test_write_and_read_batch_for_sliced_nested() {
+        // struct<ids: list<int>, name: string>
+        let ids: ArrayRef = Arc::new(ListArray::from_iter_primitive::<Int32Type, _, _>(vec![
+            Some(vec![Some(0)]),
+            Some(vec![Some(1), Some(2)]),
+            None,
+            Some(vec![Some(3), None, Some(5)]),
+            Some(vec![]),
+            Some(vec![Some(6), Some(7)]),
+            Some(vec![Some(8)]),
+        ]));
+        let names: ArrayRef = Arc::new(StringArray::from(vec![
+            Some("a"),
+            Some("b"),
+            None,
+            Some("d"),
+            Some("e"),
+            None,
+            Some("g"),
+        ]));
+        let struct_array: ArrayRef = Arc::new(StructArray::new(
+            Fields::from(vec![
+                Field::new("ids", ids.data_type().clone(), true),
+                Field::new("name", DataType::Utf8, true),
+            ]),
+            vec![ids, names],
+            Some(vec![true, true, true, false, true, true, true].into()),
+        ));
+
+        // list<struct<ids: list<int>, name: string>>, built over the struct array
+        let list_array: ArrayRef = Arc::new(ListArray::new(
+            Arc::new(Field::new("item", struct_array.data_type().clone(), true)),
+            OffsetBuffer::from_lengths([2, 0, 3, 1, 1]),
+            struct_array.clone(),
+            Some(vec![true, false, true, true, true].into()),
+        ));
+
+        // slice by odd offsets so that children of the nested arrays carry
+        // non-zero offsets
+        for batch in [
+            RecordBatch::try_from_iter_with_nullable(vec![("struct", struct_array, true)])
+                .unwrap()
+                .slice(3, 3),
+            RecordBatch::try_from_iter_with_nullable(vec![("list", list_array, true)])
+                .unwrap()
+                .slice(1, 3),
+        ] {
+            let mut buf = vec![];
+            write_batch(batch.num_rows(), batch.columns(), &mut buf).unwrap();
+            let mut cursor = Cursor::new(buf);
+            let (decoded_num_rows, decoded_cols) =
+                read_batch(&mut cursor, &batch.schema()).unwrap();
+            assert_eq!(
+                recover_named_batch(decoded_num_rows, &decoded_cols, batch.schema()).unwrap(),
+                batch
+            );
+        }
+    }
+
+    #[test]
+    fn test_write_and_read_batch_for_nested_timestamp_with_timezone() {
+        let ts: ArrayRef = Arc::new(
+            TimestampMicrosecondArray::from(vec![Some(1), None, Some(3), Some(4)])
+                .with_timezone("Asia/Shanghai"),
+        );
+        let list_array: ArrayRef = Arc::new(ListArray::new(
+            Arc::new(Field::new("item", ts.data_type().clone(), true)),
+            OffsetBuffer::from_lengths([1, 0, 3]),
+            ts.clone(),
+            None,
+        ));
+        let struct_array: ArrayRef = Arc::new(StructArray::from(vec![(
+            Arc::new(Field::new("ts", ts.data_type().clone(), true)),
+            ts.clone(),
+        )]));
+        let batch = RecordBatch::try_from_iter_with_nullable(vec![
+            ("ts", ts, true),
+            ("struct", struct_array, true),
+        ])
+        .unwrap();
+        let list_batch =
+            RecordBatch::try_from_iter_with_nullable(vec![("list", list_array, true)]).unwrap();
+
+        for batch in [batch, list_batch] {
+            let mut buf = vec![];
+            write_batch(batch.num_rows(), batch.columns(), &mut buf).unwrap();
+            let mut cursor = Cursor::new(buf);
+            let (decoded_num_rows, decoded_cols) =
+                read_batch(&mut cursor, &batch.schema()).unwrap();
+            assert_eq!(
+                recover_named_batch(decoded_num_rows, &decoded_cols, batch.schema()).unwrap(),
+                batch
+            );
+        }
+    }
 }
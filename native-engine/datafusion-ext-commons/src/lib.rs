@@ -36,6 +36,7 @@ pub mod rdxsort;
 pub mod spark_bit_array;
 pub mod spark_bloom_filter;
 pub mod spark_hash;
+pub mod spark_unsafe_row;
 pub mod uda;
 
 #[macro_export]
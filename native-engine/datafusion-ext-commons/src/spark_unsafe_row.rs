@@ -0,0 +1,758 @@
+// Copyright 2022 The Blaze Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// native implementation of org.apache.spark.sql.catalyst.expressions.UnsafeRow
+// decoding and encoding
+//
+// an UnsafeRow with N fields is laid out as:
+//  [null bitset: (N + 63) / 64 words][fixed-length region: N words][var-length
+// region]
+//
+// fixed-width values are stored little-endian in the low bytes of their word.
+// strings, binaries and decimals with precision > 18 store (offset << 32 |
+// size) in their word, where offset is relative to the start of the row, and
+// the data itself is stored in the var-length region padded to whole words.
+
+use std::sync::Arc;
+
+use arrow::{
+    array::*,
+    datatypes::*,
+    record_batch::{RecordBatch, RecordBatchOptions},
+};
+use datafusion::common::Result;
+
+use crate::{df_execution_err, df_unimplemented_err};
+
+// same as org.apache.spark.sql.types.Decimal.MAX_LONG_DIGITS
+const MAX_COMPACT_DECIMAL_PRECISION: u8 = 18;
+
+pub fn null_bitset_width_in_bytes(num_fields: usize) -> usize {
+    (num_fields + 63) / 64 * 8
+}
+
+pub fn fixed_region_width_in_bytes(num_fields: usize) -> usize {
+    null_bitset_width_in_bytes(num_fields) + num_fields * 8
+}
+
+/// Decodes Spark UnsafeRow bytes into arrow record batches.
+///
+/// a row that fails to decode may leave some of its fields appended, so the
+/// decoder must not be used any more after an error.
+pub struct UnsafeRowDecoder {
+    schema: SchemaRef,
+    field_decoders: Vec<FieldDecoder>,
+    num_rows: usize,
+}
+
+impl UnsafeRowDecoder {
+    pub fn try_new(schema: SchemaRef, capacity: usize) -> Result<Self> {
+        let field_decoders = schema
+            .fields()
+            .iter()
+            .map(|field| FieldDecoder::try_new(field.data_type(), capacity))
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            schema,
+            field_decoders,
+            num_rows: 0,
+        })
+    }
+
+    pub fn num_rows(&self) -> usize {
+        self.num_rows
+    }
+
+    pub fn append_row(&mut self, row: &[u8]) -> Result<()> {
+        let num_fields = self.field_decoders.len();
+        let bitset_width = null_bitset_width_in_bytes(num_fields);
+        if row.len() < fixed_region_width_in_bytes(num_fields) {
+            return df_execution_err!(
+                "invalid unsafe row: expect at least {} bytes for {num_fields} fields, got {}",
+                fixed_region_width_in_bytes(num_fields),
+                row.len(),
+            );
+        }
+
+        for (ordinal, field_decoder) in self.field_decoders.iter_mut().enumerate() {
+            let is_null = row[ordinal / 8] & (1 << (ordinal % 8)) != 0;
+            let word_offset = bitset_width + ordinal * 8;
+            let word: [u8; 8] = row[word_offset..][..8].try_into().unwrap();
+            field_decoder.append(row, word, is_null)?;
+        }
+        self.num_rows += 1;
+        Ok(())
+    }
+
+    pub fn finish(&mut self) -> Result<RecordBatch> {
+        let columns = self
+            .field_decoders
+            .iter_mut()
+            .map(|field_decoder| field_decoder.finish())
+            .collect::<Result<Vec<_>>>()?;
+        let num_rows = std::mem::take(&mut self.num_rows);
+        Ok(RecordBatch::try_new_with_options(
+            self.schema.clone(),
+            columns,
+            &RecordBatchOptions::new().with_row_count(Some(num_rows)),
+        )?)
+    }
+}
+
+/// Decodes an iterator of UnsafeRow bytes into record batches with at most
+/// `batch_size` rows each. The iterator ends after the first error.
+pub fn decode_unsafe_rows<'a>(
+    schema: SchemaRef,
+    rows: impl IntoIterator<Item = &'a [u8]>,
+    batch_size: usize,
+) -> Result<impl Iterator<Item = Result<RecordBatch>>> {
+    let batch_size = batch_size.max(1);
+    let mut decoder = UnsafeRowDecoder::try_new(schema, batch_size)?;
+    let mut rows = rows.into_iter().fuse();
+    let mut failed = false;
+
+    Ok(std::iter::from_fn(move || {
+        if failed {
+            return None;
+        }
+        for row in rows.by_ref() {
+            if let Err(err) = decoder.append_row(row) {
+                // the decoder may hold a partially appended row, stop here
+                failed = true;
+                return Some(Err(err));
+            }
+            if decoder.num_rows() >= batch_size {
+                return Some(decoder.finish());
+            }
+        }
+        if decoder.num_rows() > 0 {
+            return Some(decoder.finish());
+        }
+        None
+    }))
+}
+
+/// Encodes rows of a record batch into Spark UnsafeRow bytes, producing
+/// the same bytes as spark's UnsafeRowWriter.
+pub struct UnsafeRowEncoder {
+    field_encoders: Vec<FieldEncoder>,
+    num_rows: usize,
+}
+
+impl UnsafeRowEncoder {
+    pub fn try_new(batch: &RecordBatch) -> Result<Self> {
+        let field_encoders = batch
+            .columns()
+            .iter()
+            .map(FieldEncoder::try_new)
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            field_encoders,
+            num_rows: batch.num_rows(),
+        })
+    }
+
+    pub fn num_rows(&self) -> usize {
+        self.num_rows
+    }
+
+    /// appends the encoded bytes of the specified row to `buf`. offsets of
+    /// var-length fields are relative to the start of the appended row.
+    pub fn write_row(&self, row_idx: usize, buf: &mut Vec<u8>) {
+        let num_fields = self.field_encoders.len();
+        let row_start = buf.len();
+        let bitset_width = null_bitset_width_in_bytes(num_fields);
+        buf.resize(row_start + fixed_region_width_in_bytes(num_fields), 0);
+
+        for (ordinal, field_encoder) in self.field_encoders.iter().enumerate() {
+            let word_offset = row_start + bitset_width + ordinal * 8;
+            if !field_encoder.write(row_idx, row_start, word_offset, buf) {
+                buf[row_start + ordinal / 8] |= 1 << (ordinal % 8);
+            }
+        }
+    }
+}
+
+/// Encodes all rows of a record batch into UnsafeRow bytes, one buffer per
+/// row.
+pub fn encode_unsafe_rows(batch: &RecordBatch) -> Result<impl Iterator<Item = Vec<u8>>> {
+    let encoder = UnsafeRowEncoder::try_new(batch)?;
+    Ok((0..encoder.num_rows()).map(move |row_idx| {
+        let mut buf = vec![];
+        encoder.write_row(row_idx, &mut buf);
+        buf
+    }))
+}
+
+enum FieldDecoder {
+    Null(usize),
+    Boolean(BooleanBuilder),
+    Int8(Int8Builder),
+    Int16(Int16Builder),
+    Int32(Int32Builder),
+    Int64(Int64Builder),
+    Float32(Float32Builder),
+    Float64(Float64Builder),
+    Date32(Date32Builder),
+    TimestampMicrosecond(TimestampMicrosecondBuilder),
+    CompactDecimal128(Decimal128Builder),
+    Decimal128(Decimal128Builder),
+    Utf8(StringBuilder),
+    Binary(BinaryBuilder),
+}
+
+impl FieldDecoder {
+    fn try_new(data_type: &DataType, capacity: usize) -> Result<Self> {
+        Ok(match data_type {
+            DataType::Null => Self::Null(0),
+            DataType::Boolean => Self::Boolean(BooleanBuilder::with_capacity(capacity)),
+            DataType::Int8 => Self::Int8(Int8Builder::with_capacity(capacity)),
+            DataType::Int16 => Self::Int16(Int16Builder::with_capacity(capacity)),
+            DataType::Int32 => Self::Int32(Int32Builder::with_capacity(capacity)),
+            DataType::Int64 => Self::Int64(Int64Builder::with_capacity(capacity)),
+            DataType::Float32 => Self::Float32(Float32Builder::with_capacity(capacity)),
+            DataType::Float64 => Self::Float64(Float64Builder::with_capacity(capacity)),
+            DataType::Date32 => Self::Date32(Date32Builder::with_capacity(capacity)),
+            DataType::Timestamp(TimeUnit::Microsecond, tz) => Self::TimestampMicrosecond(
+                TimestampMicrosecondBuilder::with_capacity(capacity).with_timezone_opt(tz.clone()),
+            ),
+            &DataType::Decimal128(prec, scale) => {
+                let builder = Decimal128Builder::with_capacity(capacity)
+                    .with_precision_and_scale(prec, scale)?;
+                if prec <= MAX_COMPACT_DECIMAL_PRECISION {
+                    Self::CompactDecimal128(builder)
+                } else {
+                    Self::Decimal128(builder)
+                }
+            }
+            DataType::Utf8 => Self::Utf8(StringBuilder::with_capacity(capacity, 0)),
+            DataType::Binary => Self::Binary(BinaryBuilder::with_capacity(capacity, 0)),
+            other => df_unimplemented_err!("unsupported data type in unsafe row: {other}")?,
+        })
+    }
+
+    fn append(&mut self, row: &[u8], word: [u8; 8], is_null: bool) -> Result<()> {
+        macro_rules! append_fixed {
+            ($builder:expr, $native:ty) => {{
+                if is_null {
+                    $builder.append_null();
+                } else {
+                    const WIDTH: usize = std::mem::size_of::<$native>();
+                    let bytes: [u8; WIDTH] = word[..WIDTH].try_into().unwrap();
+                    $builder.append_value(<$native>::from_le_bytes(bytes));
+                }
+            }};
+        }
+
+        match self {
+            Self::Null(len) => *len += 1,
+            Self::Boolean(builder) => {
+                if is_null {
+                    builder.append_null();
+                } else {
+                    builder.append_value(word[0] != 0);
+                }
+            }
+            Self::Int8(builder) => append_fixed!(builder, i8),
+            Self::Int16(builder) => append_fixed!(builder, i16),
+            Self::Int32(builder) => append_fixed!(builder, i32),
+            Self::Int64(builder) => append_fixed!(builder, i64),
+            Self::Float32(builder) => append_fixed!(builder, f32),
+            Self::Float64(builder) => append_fixed!(builder, f64),
+            Self::Date32(builder) => append_fixed!(builder, i32),
+            Self::TimestampMicrosecond(builder) => append_fixed!(builder, i64),
+            Self::CompactDecimal128(builder) => {
+                if is_null {
+                    builder.append_null();
+                } else {
+                    builder.append_value(i64::from_le_bytes(word) as i128);
+                }
+            }
+            Self::Decimal128(builder) => {
+                if is_null {
+                    builder.append_null();
+                } else {
+                    // unscaled value is written as java BigInteger.toByteArray(),
+                    // which is big-endian two's complement with minimal length
+                    let bytes = var_length_bytes(row, word)?;
+                    if bytes.is_empty() || bytes.len() > 16 {
+                        return df_execution_err!(
+                            "invalid unsafe row: decimal with {} bytes",
+                            bytes.len()
+                        );
+                    }
+                    let sign_byte = if bytes[0] & 0x80 != 0 { 0xff } else { 0x00 };
+                    let mut be_bytes = [sign_byte; 16];
+                    be_bytes[16 - bytes.len()..].copy_from_slice(bytes);
+                    builder.append_value(i128::from_be_bytes(be_bytes));
+                }
+            }
+            Self::Utf8(builder) => {
+                if is_null {
+                    builder.append_null();
+                } else {
+                    let bytes = var_length_bytes(row, word)?;
+                    match std::str::from_utf8(bytes) {
+                        Ok(value) => builder.append_value(value),
+                        Err(err) => df_execution_err!("invalid unsafe row: {err}")?,
+                    }
+                }
+            }
+            Self::Binary(builder) => {
+                if is_null {
+                    builder.append_null();
+                } else {
+                    builder.append_value(var_length_bytes(row, word)?);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<ArrayRef> {
+        Ok(match self {
+            Self::Null(len) => Arc::new(NullArray::new(std::mem::take(len))),
+            Self::Boolean(builder) => Arc::new(builder.finish()),
+            Self::Int8(builder) => Arc::new(builder.finish()),
+            Self::Int16(builder) => Arc::new(builder.finish()),
+            Self::Int32(builder) => Arc::new(builder.finish()),
+            Self::Int64(builder) => Arc::new(builder.finish()),
+            Self::Float32(builder) => Arc::new(builder.finish()),
+            Self::Float64(builder) => Arc::new(builder.finish()),
+            Self::Date32(builder) => Arc::new(builder.finish()),
+            Self::TimestampMicrosecond(builder) => Arc::new(builder.finish()),
+            Self::CompactDecimal128(builder) => Arc::new(builder.finish()),
+            Self::Decimal128(builder) => Arc::new(builder.finish()),
+            Self::Utf8(builder) => Arc::new(builder.finish()),
+            Self::Binary(builder) => Arc::new(builder.finish()),
+        })
+    }
+}
+
+enum FieldEncoder {
+    Null,
+    Boolean(BooleanArray),
+    Int8(Int8Array),
+    Int16(Int16Array),
+    Int32(Int32Array),
+    Int64(Int64Array),
+    Float32(Float32Array),
+    Float64(Float64Array),
+    Date32(Date32Array),
+    TimestampMicrosecond(TimestampMicrosecondArray),
+    CompactDecimal128(Decimal128Array),
+    Decimal128(Decimal128Array),
+    Utf8(StringArray),
+    Binary(BinaryArray),
+}
+
+impl FieldEncoder {
+    fn try_new(array: &ArrayRef) -> Result<Self> {
+        Ok(match array.data_type() {
+            DataType::Null => Self::Null,
+            DataType::Boolean => Self::Boolean(array.as_boolean().clone()),
+            DataType::Int8 => Self::Int8(array.as_primitive().clone()),
+            DataType::Int16 => Self::Int16(array.as_primitive().clone()),
+            DataType::Int32 => Self::Int32(array.as_primitive().clone()),
+            DataType::Int64 => Self::Int64(array.as_primitive().clone()),
+            DataType::Float32 => Self::Float32(array.as_primitive().clone()),
+            DataType::Float64 => Self::Float64(array.as_primitive().clone()),
+            DataType::Date32 => Self::Date32(array.as_primitive().clone()),
+            DataType::Timestamp(TimeUnit::Microsecond, _) => {
+                Self::TimestampMicrosecond(array.as_primitive().clone())
+            }
+            &DataType::Decimal128(prec, _) => {
+                if prec <= MAX_COMPACT_DECIMAL_PRECISION {
+                    Self::CompactDecimal128(array.as_primitive().clone())
+                } else {
+                    Self::Decimal128(array.as_primitive().clone())
+                }
+            }
+            DataType::Utf8 => Self::Utf8(array.as_string().clone()),
+            DataType::Binary => Self::Binary(array.as_binary().clone()),
+            other => df_unimplemented_err!("unsupported data type in unsafe row: {other}")?,
+        })
+    }
+
+    // writes the field into its word and the var-length region, returns false
+    // if the field is null
+    fn write(
+        &self,
+        row_idx: usize,
+        row_start: usize,
+        word_offset: usize,
+        buf: &mut Vec<u8>,
+    ) -> bool {
+        macro_rules! write_fixed {
+            ($array:expr) => {{
+                if $array.is_null(row_idx) {
+                    return false;
+                }
+                let bytes = $array.value(row_idx).to_le_bytes();
+                buf[word_offset..][..bytes.len()].copy_from_slice(&bytes);
+            }};
+        }
+
+        match self {
+            Self::Null => return false,
+            Self::Boolean(array) => {
+                if array.is_null(row_idx) {
+                    return false;
+                }
+                buf[word_offset] = array.value(row_idx) as u8;
+            }
+            Self::Int8(array) => write_fixed!(array),
+            Self::Int16(array) => write_fixed!(array),
+            Self::Int32(array) => write_fixed!(array),
+            Self::Int64(array) => write_fixed!(array),
+            Self::Float32(array) => write_fixed!(array),
+            Self::Float64(array) => write_fixed!(array),
+            Self::Date32(array) => write_fixed!(array),
+            Self::TimestampMicrosecond(array) => write_fixed!(array),
+            Self::CompactDecimal128(array) => {
+                if array.is_null(row_idx) {
+                    return false;
+                }
+                let bytes = (array.value(row_idx) as i64).to_le_bytes();
+                buf[word_offset..][..8].copy_from_slice(&bytes);
+            }
+            Self::Decimal128(array) => {
+                // spark always reserves 16 bytes for non-compact decimals, even
+                // if the value is null
+                let offset = buf.len() - row_start;
+                buf.resize(buf.len() + 16, 0);
+                if array.is_null(row_idx) {
+                    set_offset_and_size(buf, word_offset, offset, 0);
+                    return false;
+                }
+                let bytes = minimal_be_bytes(array.value(row_idx));
+                buf[row_start + offset..][..bytes.len()].copy_from_slice(bytes.as_slice());
+                set_offset_and_size(buf, word_offset, offset, bytes.len());
+            }
+            Self::Utf8(array) => {
+                if array.is_null(row_idx) {
+                    return false;
+                }
+                write_var_length_bytes(
+                    buf,
+                    row_start,
+                    word_offset,
+                    array.value(row_idx).as_bytes(),
+                );
+            }
+            Self::Binary(array) => {
+                if array.is_null(row_idx) {
+                    return false;
+                }
+                write_var_length_bytes(buf, row_start, word_offset, array.value(row_idx));
+            }
+        }
+        true
+    }
+}
+
+fn var_length_bytes(row: &[u8], word: [u8; 8]) -> Result<&[u8]> {
+    let offset_and_size = u64::from_le_bytes(word);
+    let offset = (offset_and_size >> 32) as usize;
+    let size = (offset_and_size & 0xffffffff) as usize;
+    if offset + size > row.len() {
+        return df_execution_err!(
+            "invalid unsafe row: var-length field [{offset}, {}) out of row bounds {}",
+            offset + size,
+            row.len(),
+        );
+    }
+    Ok(&row[offset..][..size])
+}
+
+fn write_var_length_bytes(buf: &mut Vec<u8>, row_start: usize, word_offset: usize, bytes: &[u8]) {
+    let offset = buf.len() - row_start;
+    buf.extend_from_slice(bytes);
+    buf.resize(buf.len() + (8 - bytes.len() % 8) % 8, 0);
+    set_offset_and_size(buf, word_offset, offset, bytes.len());
+}
+
+fn set_offset_and_size(buf: &mut [u8], word_offset: usize, offset: usize, size: usize) {
+    let offset_and_size = (offset as u64) << 32 | size as u64;
+    buf[word_offset..][..8].copy_from_slice(&offset_and_size.to_le_bytes());
+}
+
+// same as java BigInteger.toByteArray(): big-endian two's complement with
+// minimal length
+fn minimal_be_bytes(value: i128) -> Vec<u8> {
+    let bytes = value.to_be_bytes();
+    let mut start = 0;
+    while start < 15 {
+        let redundant = match bytes[start] {
+            0x00 => bytes[start + 1] & 0x80 == 0,
+            0xff => bytes[start + 1] & 0x80 != 0,
+            _ => false,
+        };
+        if !redundant {
+            break;
+        }
+        start += 1;
+    }
+    bytes[start..].to_vec()
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use arrow::{array::*, datatypes::*, record_batch::RecordBatch};
+    use datafusion::common::Result;
+
+    use super::*;
+
+    fn test_schema() -> SchemaRef {
+        Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int32, true),
+            Field::new("name", DataType::Utf8, true),
+            Field::new("amount", DataType::Decimal128(10, 2), true),
+            Field::new("big", DataType::Decimal128(24, 2), true),
+            Field::new("flag", DataType::Boolean, true),
+        ]))
+    }
+
+    // UnsafeRow bytes of the following rows, written by hand following spark's
+    // layout. UnsafeRowFixturesSuite can check them against UnsafeProjection.
+    // (1, "hello", 123.45, null, true)
+    // (null, "", -1.00, 12345678901234567890.12, false)
+    // (-7, "你好", null, -0.01, null)
+    fn golden_rows() -> Vec<Vec<u8>> {
+        fixture_rows(include_bytes!("../testdata/unsafe_row/golden_rows.bin"))
+    }
+
+    fn fixture_rows(mut fixture: &[u8]) -> Vec<Vec<u8>> {
+        let mut rows = vec![];
+        while !fixture.is_empty() {
+            let len = u32::from_le_bytes(fixture[..4].try_into().unwrap()) as usize;
+            rows.push(fixture[4..][..len].to_vec());
+            fixture = &fixture[4 + len..];
+        }
+        rows
+    }
+
+    fn golden_batch() -> Result<RecordBatch> {
+        Ok(RecordBatch::try_new(
+            test_schema(),
+            vec![
+                Arc::new(Int32Array::from(vec![Some(1), None, Some(-7)])),
+                Arc::new(StringArray::from(vec![
+                    Some("hello"),
+                    Some(""),
+                    Some("你好"),
+                ])),
+                Arc::new(
+                    Decimal128Array::from(vec![Some(12345), Some(-100), None])
+                        .with_precision_and_scale(10, 2)?,
+                ),
+                Arc::new(
+                    Decimal128Array::from(vec![None, Some(1234567890123456789012), Some(-1)])
+                        .with_precision_and_scale(24, 2)?,
+                ),
+                Arc::new(BooleanArray::from(vec![Some(true), Some(false), None])),
+            ],
+        )?)
+    }
+
+    #[test]
+    fn test_decode_golden_rows() -> Result<()> {
+        let rows = golden_rows();
+        let mut decoder = UnsafeRowDecoder::try_new(test_schema(), 0)?;
+        for row in &rows {
+            decoder.append_row(row)?;
+        }
+        assert_eq!(decoder.finish()?, golden_batch()?);
+        assert_eq!(decoder.num_rows(), 0);
+        Ok(())
+    }
+
+    // a single row with all other supported primitive types, written by hand
+    // like golden_rows() and covered by UnsafeRowFixturesSuite
+    fn primitive_row_and_batch() -> Result<(Vec<u8>, RecordBatch)> {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("i8", DataType::Int8, true),
+            Field::new("i16", DataType::Int16, true),
+            Field::new("i64", DataType::Int64, true),
+            Field::new("f32", DataType::Float32, true),
+            Field::new("f64", DataType::Float64, true),
+            Field::new("date", DataType::Date32, true),
+            Field::new(
+                "ts",
+                DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into())),
+                true,
+            ),
+            Field::new("bin", DataType::Binary, true),
+        ]));
+        let row = fixture_rows(include_bytes!("../testdata/unsafe_row/primitives.bin")).remove(0);
+        let batch = RecordBatch::try_new(
+            schema,
+            vec![
+                Arc::new(Int8Array::from(vec![-2])),
+                Arc::new(Int16Array::from(vec![300])),
+                Arc::new(Int64Array::from(vec![-1])),
+                Arc::new(Float32Array::from(vec![1.5])),
+                Arc::new(Float64Array::from(vec![-0.25])),
+                Arc::new(Date32Array::from(vec![-1])),
+                Arc::new(TimestampMicrosecondArray::from(vec![None]).with_timezone("UTC")),
+                Arc::new(BinaryArray::from(vec![Some(&[1u8, 2, 3][..])])),
+            ],
+        )?;
+        Ok((row, batch))
+    }
+
+    #[test]
+    fn test_decode_primitives() -> Result<()> {
+        let (row, expected) = primitive_row_and_batch()?;
+        let mut decoder = UnsafeRowDecoder::try_new(expected.schema(), 1)?;
+        decoder.append_row(&row)?;
+        assert_eq!(decoder.finish()?, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_decode_unsafe_rows_into_batches() -> Result<()> {
+        let rows = golden_rows();
+        let batches = decode_unsafe_rows(test_schema(), rows.iter().map(|row| row.as_slice()), 2)?
+            .collect::<Result<Vec<_>>>()?;
+        assert_eq!(batches.len(), 2);
+        assert_eq!(batches[0], golden_batch()?.slice(0, 2));
+        assert_eq!(batches[1], golden_batch()?.slice(2, 1));
+        Ok(())
+    }
+
+    #[test]
+    fn test_decode_unsafe_rows_stops_after_error() -> Result<()> {
+        let mut rows = golden_rows();
+        rows[1].truncate(8);
+        let mut batches =
+            decode_unsafe_rows(test_schema(), rows.iter().map(|row| row.as_slice()), 2)?;
+        assert!(batches.next().unwrap().is_err());
+        assert!(batches.next().is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_decode_invalid_rows() -> Result<()> {
+        let mut decoder = UnsafeRowDecoder::try_new(test_schema(), 0)?;
+        assert!(decoder.append_row(&[0u8; 40]).is_err());
+
+        // var-length field pointing out of the row
+        let mut decoder = UnsafeRowDecoder::try_new(test_schema(), 0)?;
+        let mut row = golden_rows().remove(0);
+        row[16..24].copy_from_slice(&(100u64 << 32 | 5).to_le_bytes());
+        assert!(decoder.append_row(&row).is_err());
+
+        assert!(UnsafeRowDecoder::try_new(
+            Arc::new(Schema::new(vec![Field::new(
+                "list",
+                DataType::new_list(DataType::Int32, true),
+                true,
+            )])),
+            0,
+        )
+        .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_encode_golden_rows() -> Result<()> {
+        let rows = encode_unsafe_rows(&golden_batch()?)?.collect::<Vec<_>>();
+        assert_eq!(rows, golden_rows());
+
+        // `big` is a null non-compact decimal in the first row, its 16 bytes
+        // are still reserved in the var-length region
+        assert!(golden_batch()?.column(3).is_null(0));
+        assert_eq!(rows[0][32..40], (56u64 << 32).to_le_bytes());
+        assert_eq!(rows[0].len(), 72);
+
+        // re-encoding decoded fixture rows gives back the same bytes
+        let mut decoder = UnsafeRowDecoder::try_new(test_schema(), 0)?;
+        for row in &golden_rows() {
+            decoder.append_row(row)?;
+        }
+        let rows = encode_unsafe_rows(&decoder.finish()?)?.collect::<Vec<_>>();
+        assert_eq!(rows, golden_rows());
+        Ok(())
+    }
+
+    #[test]
+    fn test_encode_primitives() -> Result<()> {
+        let (row, expected) = primitive_row_and_batch()?;
+        assert_eq!(
+            encode_unsafe_rows(&expected)?.collect::<Vec<_>>(),
+            vec![row]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_encode_decode_roundtrip() -> Result<()> {
+        let batch = RecordBatch::try_new(
+            Arc::new(Schema::new(vec![
+                Field::new("null", DataType::Null, true),
+                Field::new("s", DataType::Utf8, true),
+                Field::new("d", DataType::Decimal128(38, 0), true),
+            ])),
+            vec![
+                Arc::new(NullArray::new(4)),
+                Arc::new(StringArray::from(vec![
+                    Some("12345678"),
+                    None,
+                    Some("123456789"),
+                    Some("a"),
+                ])),
+                Arc::new(
+                    Decimal128Array::from(vec![
+                        Some(i128::MAX / 2),
+                        Some(-i128::MAX / 2),
+                        Some(0),
+                        Some(128),
+                    ])
+                    .with_precision_and_scale(38, 0)?,
+                ),
+            ],
+        )?;
+
+        // write all rows into a shared buffer to check offsets are row-relative
+        let encoder = UnsafeRowEncoder::try_new(&batch)?;
+        let mut buf = vec![];
+        let mut row_ends = vec![];
+        for row_idx in 0..encoder.num_rows() {
+            encoder.write_row(row_idx, &mut buf);
+            assert_eq!(buf.len() % 8, 0);
+            row_ends.push(buf.len());
+        }
+        let rows = std::iter::once(0)
+            .chain(row_ends.iter().cloned())
+            .zip(row_ends.iter().cloned())
+            .map(|(start, end)| &buf[start..end]);
+        let batches = decode_unsafe_rows(batch.schema(), rows, 3)?.collect::<Result<Vec<_>>>()?;
+        assert_eq!(batches, vec![batch.slice(0, 3), batch.slice(3, 1)]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_minimal_be_bytes() {
+        assert_eq!(minimal_be_bytes(0), vec![0x00]);
+        assert_eq!(minimal_be_bytes(-1), vec![0xff]);
+        assert_eq!(minimal_be_bytes(127), vec![0x7f]);
+        assert_eq!(minimal_be_bytes(128), vec![0x00, 0x80]);
+        assert_eq!(minimal_be_bytes(-128), vec![0x80]);
+        assert_eq!(minimal_be_bytes(-129), vec![0xff, 0x7f]);
+        assert_eq!(minimal_be_bytes(i128::MIN).len(), 16);
+    }
+}
@@ -15,23 +15,102 @@
 // specific language governing permissions and limitations
 // under the License.
 
-use std::io::{BufReader, Read, Take, Write};
+use std::{
+    fmt::{Display, Formatter},
+    io::{BufReader, Cursor, Read, Take, Write},
+};
 
 use arrow::{array::ArrayRef, datatypes::SchemaRef};
-use blaze_jni_bridge::{conf, conf::StringConf, is_jni_bridge_inited};
+use blaze_jni_bridge::{
+    conf,
+    conf::{BooleanConf, IntConf, StringConf},
+    is_jni_bridge_inited,
+};
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
-use datafusion::common::Result;
+use datafusion::{common::Result, physical_plan::metrics::Count};
 use datafusion_ext_commons::{
+    array_size::{column_estimated_sizes, ArraySize},
     df_execution_err,
     io::{read_one_batch, write_one_batch},
 };
 use once_cell::sync::OnceCell;
 
 pub const DEFAULT_SHUFFLE_COMPRESSION_TARGET_BUF_SIZE: usize = 4194304;
-const ZSTD_LEVEL: i32 = 1;
+const DEFAULT_ZSTD_LEVEL: i32 = 1;
+const DEFAULT_MAX_FRAME_SIZE: usize = 268435456;
+
+// every block starts with a u32 block length. if the highest bit is set, the
+// length is followed by a one-byte codec tag, otherwise the block is written by
+// older versions and is compressed with spark.io.compression.codec.
+// if the highest bit of the tag is set, the tag is followed by a u32 crc32c
+// (castagnoli) checksum of the compressed block data.
+const BLOCK_TAGGED_FLAG: u32 = 1 << 31;
+const BLOCK_CHECKSUM_FLAG: u8 = 1 << 7;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IoCompressionCodec {
+    None,
+    Lz4,
+    Zstd(i32),
+}
+
+impl IoCompressionCodec {
+    pub fn try_from_name(name: &str, zstd_level: i32) -> Result<Self> {
+        match name {
+            "none" => Ok(Self::None),
+            "lz4" => Ok(Self::Lz4),
+            "zstd" => Ok(Self::Zstd(zstd_level)),
+            _ => df_execution_err!("unsupported codec: {}", name),
+        }
+    }
+
+    fn tag(&self) -> u8 {
+        match self {
+            Self::None => 0,
+            Self::Lz4 => 1,
+            Self::Zstd(_) => 2,
+        }
+    }
+
+    fn try_from_tag(tag: u8) -> Result<Self> {
+        match tag {
+            0 => Ok(Self::None),
+            1 => Ok(Self::Lz4),
+            2 => Ok(Self::Zstd(DEFAULT_ZSTD_LEVEL)), // level is not used in decompression
+            _ => df_execution_err!("unsupported codec tag: {}", tag),
+        }
+    }
+}
+
+/// Error returned by [`IpcCompressionReader`] when the checksum of a block
+/// does not match its data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IpcChecksumError {
+    pub block_offset: u64,
+    pub block_index: usize,
+    pub expected: u32,
+    pub actual: u32,
+}
+
+impl Display for IpcChecksumError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "ipc compression block checksum mismatch (block_offset={}, block_index={}): \
+             expected {:08x}, actual {:08x}",
+            self.block_offset, self.block_index, self.expected, self.actual,
+        )
+    }
+}
+
+impl std::error::Error for IpcChecksumError {}
 
 pub struct IpcCompressionWriter<W: Write> {
     output: W,
+    codec: IoCompressionCodec,
+    checksum: bool,
+    max_frame_size: usize,
+    oversized_frame_count: Count,
     shared_buf: VecBuffer,
     block_writer: IoCompressionWriter<VecBufferWrite>,
     block_empty: bool,
@@ -40,18 +119,42 @@ unsafe impl<W: Write> Send for IpcCompressionWriter<W> {}
 
 impl<W: Write> IpcCompressionWriter<W> {
     pub fn new(output: W) -> Self {
-        let mut shared_buf = VecBuffer::default();
-        shared_buf.inner_mut().extend_from_slice(&[0u8; 4]);
+        Self::new_with_codec(output, io_compression_codec(), io_checksum_enabled())
+    }
 
-        let block_writer = IoCompressionWriter::new_with_configured_codec(shared_buf.writer());
+    pub fn new_with_codec(output: W, codec: IoCompressionCodec, checksum: bool) -> Self {
+        let mut shared_buf = VecBuffer::default();
+        let block_writer = open_block(&mut shared_buf, codec, checksum);
         Self {
             output,
+            codec,
+            checksum,
+            max_frame_size: usize::MAX,
+            oversized_frame_count: Count::new(),
             shared_buf,
             block_writer,
             block_empty: true,
         }
     }
 
+    /// Splits batches larger than spark.blaze.io.maxFrameSize into smaller
+    /// frames, see set_max_frame_size().
+    pub fn enable_frame_splitting(&mut self, oversized_frame_count: Count) {
+        self.set_max_frame_size(io_max_frame_size(), oversized_frame_count);
+    }
+
+    /// Splits batches larger than `max_frame_size` into smaller frames, which
+    /// are read back as separate batches. single rows exceeding the limit are
+    /// still written as one frame and counted in `oversized_frame_count`.
+    ///
+    /// splitting is disabled by default and must only be enabled when readers
+    /// do not depend on batch boundaries. e.g. broadcast hash maps written by
+    /// IpcWriterExec must be read back as exactly one batch.
+    pub fn set_max_frame_size(&mut self, max_frame_size: usize, oversized_frame_count: Count) {
+        self.max_frame_size = max_frame_size;
+        self.oversized_frame_count = oversized_frame_count;
+    }
+
     pub fn set_output(&mut self, output: W) {
         assert!(
             self.block_empty,
@@ -64,6 +167,34 @@ impl<W: Write> IpcCompressionWriter<W> {
         if num_rows == 0 {
             return Ok(());
         }
+
+        // split oversized batch into smaller frames, so that the reader side
+        // never allocates a huge contiguous buffer.
+        // get_array_mem_size() is a cheap upper bound of the estimated size, so
+        // the estimation is skipped for most batches.
+        let mem_size: usize = cols.iter().map(|col| col.get_array_mem_size()).sum();
+        let estimated_size: usize = if mem_size > self.max_frame_size {
+            column_estimated_sizes(cols).into_iter().sum()
+        } else {
+            0
+        };
+        if estimated_size > self.max_frame_size {
+            if num_rows > 1 {
+                let half = num_rows / 2;
+                let (first, second): (Vec<ArrayRef>, Vec<ArrayRef>) = cols
+                    .iter()
+                    .map(|col| (col.slice(0, half), col.slice(half, num_rows - half)))
+                    .unzip();
+                self.write_batch(half, &first)?;
+                self.write_batch(num_rows - half, &second)?;
+                return Ok(());
+            }
+            log::warn!(
+                "writing single row frame exceeding max frame size: {estimated_size} > {}",
+                self.max_frame_size,
+            );
+            self.oversized_frame_count.add(1);
+        }
         write_one_batch(num_rows, cols, &mut self.block_writer)?;
         self.block_empty = false;
 
@@ -80,17 +211,24 @@ impl<W: Write> IpcCompressionWriter<W> {
             self.block_writer.finish()?;
 
             // write
-            let block_len = self.shared_buf.inner().len() - 4;
+            let header_len = block_header_len(self.checksum);
+            let block_len = self.shared_buf.inner().len() - header_len;
+            if block_len as u64 >= BLOCK_TAGGED_FLAG as u64 {
+                return df_execution_err!("ipc compression block too large: {}", block_len);
+            }
             self.shared_buf.inner_mut()[0..4]
                 .as_mut()
-                .write_u32::<LittleEndian>(block_len as u32)?;
+                .write_u32::<LittleEndian>(block_len as u32 | BLOCK_TAGGED_FLAG)?;
+            if self.checksum {
+                let checksum = crc32c::crc32c(&self.shared_buf.inner()[header_len..]);
+                self.shared_buf.inner_mut()[5..9]
+                    .as_mut()
+                    .write_u32::<LittleEndian>(checksum)?;
+            }
             self.output.write_all(self.shared_buf.inner())?;
 
             // open next buf
-            self.shared_buf.inner_mut().clear();
-            self.shared_buf.inner_mut().extend_from_slice(&[0u8; 4]);
-            self.block_writer =
-                IoCompressionWriter::new_with_configured_codec(self.shared_buf.writer());
+            self.block_writer = open_block(&mut self.shared_buf, self.codec, self.checksum);
             self.block_empty = true;
         }
         Ok(())
@@ -107,6 +245,8 @@ impl<W: Write> IpcCompressionWriter<W> {
 
 pub struct IpcCompressionReader<R: Read + 'static> {
     input: InputState<R>,
+    block_offset: u64,
+    block_index: usize,
 }
 unsafe impl<R: Read> Send for IpcCompressionReader<R> {}
 
@@ -115,13 +255,44 @@ enum InputState<R: Read + 'static> {
     #[default]
     Unreachable,
     BlockStart(R),
-    BlockContent(IoCompressionReader<Take<R>>),
+    BlockContent(Box<IoCompressionReader<BlockInput<R>>>),
+}
+
+enum BlockInput<R: Read> {
+    Unverified(Take<R>),
+    Verified(Cursor<Vec<u8>>, R),
+}
+
+impl<R: Read> BlockInput<R> {
+    fn into_inner(self) -> R {
+        match self {
+            Self::Unverified(taken) => taken.into_inner(),
+            Self::Verified(_, input) => input,
+        }
+    }
+}
+
+impl<R: Read> Read for BlockInput<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            Self::Unverified(taken) => taken.read(buf),
+            Self::Verified(block_data, _) => block_data.read(buf),
+        }
+    }
 }
 
 impl<R: Read> IpcCompressionReader<R> {
     pub fn new(input: R) -> Self {
+        Self::new_with_base_offset(input, 0)
+    }
+
+    /// Creates a reader for input starting at `base_offset` of a file, so that
+    /// block offsets in checksum errors are relative to the file.
+    pub fn new_with_base_offset(input: R, base_offset: u64) -> Self {
         Self {
             input: InputState::BlockStart(input),
+            block_offset: base_offset,
+            block_index: 0,
         }
     }
 
@@ -131,8 +302,8 @@ impl<R: Read> IpcCompressionReader<R> {
             fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
                 match std::mem::take(&mut self.0.input) {
                     InputState::BlockStart(mut input) => {
-                        let block_len = match input.read_u32::<LittleEndian>() {
-                            Ok(block_len) => block_len,
+                        let header = match input.read_u32::<LittleEndian>() {
+                            Ok(header) => header,
                             Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => {
                                 return Ok(0);
                             }
@@ -140,12 +311,54 @@ impl<R: Read> IpcCompressionReader<R> {
                                 return Err(err);
                             }
                         };
-                        let taken = input.take(block_len as u64);
+                        let (block_len, codec, checksum, header_len) =
+                            if header & BLOCK_TAGGED_FLAG != 0 {
+                                let tag = input.read_u8()?;
+                                let codec =
+                                    IoCompressionCodec::try_from_tag(tag & !BLOCK_CHECKSUM_FLAG)?;
+                                let has_checksum = tag & BLOCK_CHECKSUM_FLAG != 0;
+                                let checksum = if has_checksum {
+                                    Some(input.read_u32::<LittleEndian>()?)
+                                } else {
+                                    None
+                                };
+                                let header_len = block_header_len(has_checksum);
+                                (header & !BLOCK_TAGGED_FLAG, codec, checksum, header_len)
+                            } else {
+                                (header, legacy_io_compression_codec(), None, 4)
+                            };
+
+                        // verify the whole block before decompressing, so that no
+                        // data from a corrupted block is returned
+                        let block_input = match checksum {
+                            Some(expected) => {
+                                let mut block_data = Vec::with_capacity(block_len as usize);
+                                input
+                                    .by_ref()
+                                    .take(block_len as u64)
+                                    .read_to_end(&mut block_data)?;
+                                let actual = crc32c::crc32c(&block_data);
+                                if block_data.len() < block_len as usize || actual != expected {
+                                    return Err(std::io::Error::new(
+                                        std::io::ErrorKind::InvalidData,
+                                        IpcChecksumError {
+                                            block_offset: self.0.block_offset,
+                                            block_index: self.0.block_index,
+                                            expected,
+                                            actual,
+                                        },
+                                    ));
+                                }
+                                BlockInput::Verified(Cursor::new(block_data), input)
+                            }
+                            None => BlockInput::Unverified(input.take(block_len as u64)),
+                        };
+                        self.0.block_offset += (header_len + block_len as usize) as u64;
+                        self.0.block_index += 1;
 
-                        self.0.input = InputState::BlockContent(IoCompressionReader::try_new(
-                            io_compression_codec(),
-                            taken,
-                        )?);
+                        self.0.input = InputState::BlockContent(Box::new(
+                            IoCompressionReader::try_new(codec, block_input)?,
+                        ));
                         self.read(buf)
                     }
                     InputState::BlockContent(mut block_reader) => match block_reader.read(buf) {
@@ -168,26 +381,53 @@ impl<R: Read> IpcCompressionReader<R> {
     }
 }
 
+fn block_header_len(checksum: bool) -> usize {
+    if checksum {
+        9 // block_len + tag + checksum
+    } else {
+        5 // block_len + tag
+    }
+}
+
+fn open_block(
+    shared_buf: &mut VecBuffer,
+    codec: IoCompressionCodec,
+    checksum: bool,
+) -> IoCompressionWriter<VecBufferWrite> {
+    shared_buf.inner_mut().clear();
+    shared_buf.inner_mut().extend_from_slice(&[0u8; 4]);
+    if checksum {
+        shared_buf
+            .inner_mut()
+            .push(codec.tag() | BLOCK_CHECKSUM_FLAG);
+        shared_buf.inner_mut().extend_from_slice(&[0u8; 4]);
+    } else {
+        shared_buf.inner_mut().push(codec.tag());
+    }
+    IoCompressionWriter::try_new(codec, shared_buf.writer())
+        .expect("error creating compression encoder")
+}
+
 enum IoCompressionWriter<W: Write> {
+    None(W),
     LZ4(lz4_flex::frame::FrameEncoder<W>),
     ZSTD(zstd::Encoder<'static, W>),
 }
 
 impl<W: Write> IoCompressionWriter<W> {
-    fn new_with_configured_codec(inner: W) -> Self {
-        Self::try_new(io_compression_codec(), inner).expect("error creating compression encoder")
-    }
-
-    fn try_new(codec: &str, inner: W) -> Result<Self> {
+    fn try_new(codec: IoCompressionCodec, inner: W) -> Result<Self> {
         match codec {
-            "lz4" => Ok(Self::LZ4(lz4_flex::frame::FrameEncoder::new(inner))),
-            "zstd" => Ok(Self::ZSTD(zstd::Encoder::new(inner, ZSTD_LEVEL)?)),
-            _ => df_execution_err!("unsupported codec: {}", codec),
+            IoCompressionCodec::None => Ok(Self::None(inner)),
+            IoCompressionCodec::Lz4 => Ok(Self::LZ4(lz4_flex::frame::FrameEncoder::new(inner))),
+            IoCompressionCodec::Zstd(level) => Ok(Self::ZSTD(zstd::Encoder::new(inner, level)?)),
         }
     }
 
     fn finish(&mut self) -> Result<()> {
         match self {
+            IoCompressionWriter::None(w) => {
+                w.flush()?;
+            }
             IoCompressionWriter::LZ4(w) => {
                 w.try_finish()
                     .or_else(|_| df_execution_err!("ipc compresion error"))?;
@@ -203,6 +443,7 @@ impl<W: Write> IoCompressionWriter<W> {
 impl<W: Write> Write for IoCompressionWriter<W> {
     fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
         match self {
+            IoCompressionWriter::None(w) => w.write(buf),
             IoCompressionWriter::LZ4(w) => w.write(buf),
             IoCompressionWriter::ZSTD(w) => w.write(buf),
         }
@@ -210,6 +451,7 @@ impl<W: Write> Write for IoCompressionWriter<W> {
 
     fn flush(&mut self) -> std::io::Result<()> {
         match self {
+            IoCompressionWriter::None(w) => w.flush(),
             IoCompressionWriter::LZ4(w) => w.flush(),
             IoCompressionWriter::ZSTD(w) => w.flush(),
         }
@@ -217,21 +459,23 @@ impl<W: Write> Write for IoCompressionWriter<W> {
 }
 
 enum IoCompressionReader<R: Read> {
+    None(R),
     LZ4(lz4_flex::frame::FrameDecoder<R>),
     ZSTD(zstd::Decoder<'static, BufReader<R>>),
 }
 
 impl<R: Read> IoCompressionReader<R> {
-    fn try_new(codec: &str, inner: R) -> Result<Self> {
+    fn try_new(codec: IoCompressionCodec, inner: R) -> Result<Self> {
         match codec {
-            "lz4" => Ok(Self::LZ4(lz4_flex::frame::FrameDecoder::new(inner))),
-            "zstd" => Ok(Self::ZSTD(zstd::Decoder::new(inner)?)),
-            _ => df_execution_err!("unsupported codec: {}", codec),
+            IoCompressionCodec::None => Ok(Self::None(inner)),
+            IoCompressionCodec::Lz4 => Ok(Self::LZ4(lz4_flex::frame::FrameDecoder::new(inner))),
+            IoCompressionCodec::Zstd(_) => Ok(Self::ZSTD(zstd::Decoder::new(inner)?)),
         }
     }
 
     fn finish_into_inner(self) -> Result<R> {
         match self {
+            Self::None(r) => Ok(r),
             Self::LZ4(r) => Ok(r.into_inner()),
             Self::ZSTD(r) => Ok(r.finish().into_inner()),
         }
@@ -241,24 +485,79 @@ impl<R: Read> IoCompressionReader<R> {
 impl<R: Read> Read for IoCompressionReader<R> {
     fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
         match self {
+            Self::None(r) => r.read(buf),
             Self::LZ4(r) => r.read(buf),
             Self::ZSTD(r) => r.read(buf),
         }
     }
 }
 
-fn io_compression_codec() -> &'static str {
-    static CODEC: OnceCell<String> = OnceCell::new();
-    CODEC
+fn io_compression_codec() -> IoCompressionCodec {
+    static CODEC: OnceCell<IoCompressionCodec> = OnceCell::new();
+    *CODEC
         .get_or_try_init(|| {
             if is_jni_bridge_inited() {
-                conf::SPARK_IO_COMPRESSION_CODEC.value()
+                let mut codec_name = conf::SHUFFLE_COMPRESSION_CODEC.value()?;
+                if codec_name.is_empty() {
+                    codec_name = conf::SPARK_IO_COMPRESSION_CODEC.value()?;
+                }
+                let zstd_level = conf::SHUFFLE_COMPRESSION_ZSTD_LEVEL.value()?;
+                IoCompressionCodec::try_from_name(&codec_name, zstd_level)
             } else {
-                Ok(format!("lz4")) // for testing
+                Ok(IoCompressionCodec::Lz4) // for testing
+            }
+        })
+        .expect("error reading spark.blaze.shuffle.compression.codec")
+}
+
+pub(crate) fn io_checksum_enabled() -> bool {
+    static CHECKSUM_ENABLED: OnceCell<bool> = OnceCell::new();
+    *CHECKSUM_ENABLED
+        .get_or_try_init(|| {
+            if is_jni_bridge_inited() {
+                conf::IO_CHECKSUM_ENABLE.value()
+            } else {
+                Ok(false) // for testing
+            }
+        })
+        .expect("error reading spark.blaze.io.checksum.enable")
+}
+
+fn io_max_frame_size() -> usize {
+    static MAX_FRAME_SIZE: OnceCell<usize> = OnceCell::new();
+    *MAX_FRAME_SIZE
+        .get_or_try_init(|| {
+            if is_jni_bridge_inited() {
+                conf::IO_MAX_FRAME_SIZE
+                    .value()
+                    .and_then(check_max_frame_size)
+            } else {
+                Ok(DEFAULT_MAX_FRAME_SIZE) // for testing
+            }
+        })
+        .expect("error reading spark.blaze.io.maxFrameSize")
+}
+
+fn check_max_frame_size(size: i32) -> Result<usize> {
+    if size <= 0 {
+        return df_execution_err!("max frame size must be positive, got {size}");
+    }
+    Ok(size as usize)
+}
+
+// codec of untagged blocks written by older versions
+fn legacy_io_compression_codec() -> IoCompressionCodec {
+    static CODEC: OnceCell<IoCompressionCodec> = OnceCell::new();
+    *CODEC
+        .get_or_try_init(|| {
+            if is_jni_bridge_inited() {
+                let codec_name = conf::SPARK_IO_COMPRESSION_CODEC.value()?;
+                IoCompressionCodec::try_from_name(&codec_name, DEFAULT_ZSTD_LEVEL)
+            } else {
+                Ok(IoCompressionCodec::Lz4) // for testing
             }
         })
         .expect("error reading spark.io.compression.codec")
-        .as_str()
 }
 
 #[derive(Default)]
@@ -303,9 +602,11 @@ mod tests {
     use std::{error::Error, io::Cursor, sync::Arc};
 
     use arrow::{
-        array::StringArray,
-        datatypes::{DataType, Field, Schema},
+        array::{AsArray, BinaryArray, Decimal128Array, Int32Array, ListArray, StringArray},
+        datatypes::{DataType, Field, Int32Type, Schema},
+        record_batch::RecordBatch,
     };
+    use datafusion::common::DataFusionError;
 
     use super::*;
 
@@ -332,4 +633,263 @@ mod tests {
         assert!(reader.read_batch(&schema)?.is_none());
         Ok(())
     }
+
+    fn test_batch() -> RecordBatch {
+        let int_array: ArrayRef = Arc::new(Int32Array::from(vec![Some(1), None, Some(3), None]));
+        let str_array: ArrayRef = Arc::new(StringArray::from(vec![
+            Some("hello"),
+            Some("world"),
+            None,
+            Some(""),
+        ]));
+        let decimal_array: ArrayRef = Arc::new(
+            Decimal128Array::from(vec![Some(12345), None, Some(-67890), Some(0)])
+                .with_precision_and_scale(20, 2)
+                .unwrap(),
+        );
+        let list_array: ArrayRef =
+            Arc::new(ListArray::from_iter_primitive::<Int32Type, _, _>(vec![
+                Some(vec![Some(1), None]),
+                None,
+                Some(vec![]),
+                Some(vec![Some(4), Some(5), Some(6)]),
+            ]));
+        RecordBatch::try_from_iter(vec![
+            ("int", int_array),
+            ("str", str_array),
+            ("decimal", decimal_array),
+            ("list", list_array),
+        ])
+        .unwrap()
+    }
+
+    #[test]
+    fn test_ipc_compression_codecs() -> Result<(), Box<dyn Error>> {
+        let batch = test_batch();
+        let codecs = [
+            IoCompressionCodec::None,
+            IoCompressionCodec::Lz4,
+            IoCompressionCodec::Zstd(1),
+            IoCompressionCodec::Zstd(9),
+        ];
+
+        for codec in codecs {
+            let mut buf = vec![];
+            let mut writer = IpcCompressionWriter::new_with_codec(&mut buf, codec, false);
+            writer.write_batch(batch.num_rows(), batch.columns())?;
+            writer.finish_current_buf()?;
+            writer.write_batch(batch.num_rows(), batch.columns())?;
+            writer.finish_current_buf()?;
+            assert_eq!(buf[4], codec.tag());
+
+            let mut reader = IpcCompressionReader::new(Cursor::new(buf));
+            for _ in 0..2 {
+                let (num_rows, arrays) = reader.read_batch(&batch.schema())?.unwrap();
+                assert_eq!(num_rows, batch.num_rows());
+                assert_eq!(arrays, batch.columns());
+            }
+            assert!(reader.read_batch(&batch.schema())?.is_none());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_ipc_compression_mixed_codecs() -> Result<(), Box<dyn Error>> {
+        let batch = test_batch();
+
+        // blocks with different codecs can be concatenated into one stream
+        let mut buf = vec![];
+        for codec in [IoCompressionCodec::Zstd(3), IoCompressionCodec::None] {
+            let mut writer = IpcCompressionWriter::new_with_codec(&mut buf, codec, false);
+            writer.write_batch(batch.num_rows(), batch.columns())?;
+            writer.finish_current_buf()?;
+        }
+
+        // untagged block written by older versions
+        let mut legacy_block = vec![];
+        let mut legacy_writer =
+            IoCompressionWriter::try_new(legacy_io_compression_codec(), &mut legacy_block)?;
+        write_one_batch(batch.num_rows(), batch.columns(), &mut legacy_writer)?;
+        legacy_writer.finish()?;
+        drop(legacy_writer);
+        buf.write_u32::<LittleEndian>(legacy_block.len() as u32)?;
+        buf.extend_from_slice(&legacy_block);
+
+        let mut reader = IpcCompressionReader::new(Cursor::new(buf));
+        for _ in 0..3 {
+            let (num_rows, arrays) = reader.read_batch(&batch.schema())?.unwrap();
+            assert_eq!(num_rows, batch.num_rows());
+            assert_eq!(arrays, batch.columns());
+        }
+        assert!(reader.read_batch(&batch.schema())?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_ipc_compression_max_frame_size() -> Result<(), Box<dyn Error>> {
+        let long_str = "x".repeat(1000);
+        let str_array: ArrayRef = Arc::new(StringArray::from_iter_values((0..100).map(|i| {
+            if i == 42 {
+                &long_str
+            } else {
+                "short"
+            }
+        })));
+        let int_array: ArrayRef = Arc::new(Int32Array::from_iter_values(0..100));
+        let batch =
+            RecordBatch::try_from_iter(vec![("str", str_array), ("int", int_array)]).unwrap();
+
+        // batches are not split by default
+        let mut buf = vec![];
+        let mut writer =
+            IpcCompressionWriter::new_with_codec(&mut buf, IoCompressionCodec::Lz4, false);
+        writer.write_batch(batch.num_rows(), batch.columns())?;
+        writer.finish_current_buf()?;
+        let mut reader = IpcCompressionReader::new(Cursor::new(buf));
+        let (num_rows, arrays) = reader.read_batch(&batch.schema())?.unwrap();
+        assert_eq!(num_rows, batch.num_rows());
+        assert_eq!(arrays, batch.columns());
+        assert!(reader.read_batch(&batch.schema())?.is_none());
+
+        let mut buf = vec![];
+        let mut writer =
+            IpcCompressionWriter::new_with_codec(&mut buf, IoCompressionCodec::Lz4, false);
+        let oversized_frame_count = Count::new();
+        writer.set_max_frame_size(300, oversized_frame_count.clone());
+        writer.write_batch(batch.num_rows(), batch.columns())?;
+        writer.write_batch(0, batch.slice(0, 0).columns())?;
+        writer.finish_current_buf()?;
+        assert_eq!(oversized_frame_count.value(), 1);
+
+        // frames are returned as separate batches, with the oversized row in
+        // its own frame
+        let mut reader = IpcCompressionReader::new(Cursor::new(buf));
+        let mut batches = vec![];
+        while let Some((num_rows, arrays)) = reader.read_batch(&batch.schema())? {
+            assert!(num_rows > 0);
+            batches.push(RecordBatch::try_new(batch.schema(), arrays)?);
+        }
+        assert!(batches.len() > 1);
+        assert!(batches
+            .iter()
+            .any(|b| b.num_rows() == 1 && b.column(0).as_string::<i32>().value(0) == long_str));
+        assert_eq!(
+            arrow::compute::concat_batches(&batch.schema(), &batches)?,
+            batch
+        );
+
+        assert_eq!(check_max_frame_size(1024)?, 1024);
+        assert!(check_max_frame_size(0).is_err());
+        assert!(check_max_frame_size(-1).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_ipc_compression_hash_map_batch() -> Result<(), Box<dyn Error>> {
+        // shaped like the output of BroadcastJoinBuildHashMapExec: the whole hash
+        // map is in row 0 of the last column, and readers expect a single batch
+        let num_rows = 1000;
+        let int_array: ArrayRef = Arc::new(Int32Array::from_iter_values(0..num_rows));
+        let table = vec![7u8; 100000];
+        let table_array: ArrayRef = Arc::new(BinaryArray::from_iter(
+            (0..num_rows).map(|i| (i == 0).then_some(table.as_slice())),
+        ));
+        let batch = RecordBatch::try_from_iter(vec![("int", int_array), ("~TABLE", table_array)])?;
+
+        let write_and_read = |max_frame_size: Option<usize>| -> Result<Vec<RecordBatch>> {
+            let mut buf = vec![];
+            let mut writer =
+                IpcCompressionWriter::new_with_codec(&mut buf, IoCompressionCodec::Lz4, false);
+            if let Some(max_frame_size) = max_frame_size {
+                writer.set_max_frame_size(max_frame_size, Count::new());
+            }
+            writer.write_batch(batch.num_rows(), batch.columns())?;
+            writer.finish_current_buf()?;
+
+            let mut reader = IpcCompressionReader::new(Cursor::new(buf));
+            let mut batches = vec![];
+            while let Some((_, arrays)) = reader.read_batch(&batch.schema())? {
+                batches.push(RecordBatch::try_new(batch.schema(), arrays)?);
+            }
+            Ok(batches)
+        };
+
+        // IpcWriterExec does not enable splitting, so the batch stays whole
+        assert_eq!(write_and_read(None)?, vec![batch.clone()]);
+
+        // it would be split if splitting were enabled
+        assert!(write_and_read(Some(65536))?.len() > 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_ipc_compression_corrupted_codec_tag() -> Result<(), Box<dyn Error>> {
+        let batch = test_batch();
+        let mut buf = vec![];
+        let mut writer =
+            IpcCompressionWriter::new_with_codec(&mut buf, IoCompressionCodec::Lz4, false);
+        writer.write_batch(batch.num_rows(), batch.columns())?;
+        writer.finish_current_buf()?;
+        buf[4] = 0x7f;
+
+        let mut reader = IpcCompressionReader::new(Cursor::new(buf));
+        let err = reader.read_batch(&batch.schema()).unwrap_err();
+        assert!(err.to_string().contains("unsupported codec tag: 127"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_ipc_compression_checksum() -> Result<(), Box<dyn Error>> {
+        let batch = test_batch();
+        let mut buf = vec![];
+        let mut writer =
+            IpcCompressionWriter::new_with_codec(&mut buf, IoCompressionCodec::Lz4, true);
+        writer.write_batch(batch.num_rows(), batch.columns())?;
+        writer.finish_current_buf()?;
+        let second_block_offset = writer.inner().len();
+        writer.write_batch(batch.num_rows(), batch.columns())?;
+        writer.finish_current_buf()?;
+        assert_eq!(
+            buf[second_block_offset + 4],
+            IoCompressionCodec::Lz4.tag() | BLOCK_CHECKSUM_FLAG
+        );
+
+        // verified blocks can be read
+        let mut reader = IpcCompressionReader::new(Cursor::new(buf.clone()));
+        for _ in 0..2 {
+            let (num_rows, arrays) = reader.read_batch(&batch.schema())?.unwrap();
+            assert_eq!(num_rows, batch.num_rows());
+            assert_eq!(arrays, batch.columns());
+        }
+        assert!(reader.read_batch(&batch.schema())?.is_none());
+
+        // flip a byte in the middle of the second block
+        let corrupted_pos = second_block_offset + (buf.len() - second_block_offset) / 2;
+        buf[corrupted_pos] ^= 0xff;
+
+        // read from the middle of a file, block offsets are relative to the file
+        let base_offset = 100;
+        let mut file = Cursor::new([vec![0u8; base_offset], buf].concat());
+        file.set_position(base_offset as u64);
+        let mut reader = IpcCompressionReader::new_with_base_offset(file, base_offset as u64);
+        let (num_rows, arrays) = reader.read_batch(&batch.schema())?.unwrap();
+        assert_eq!(num_rows, batch.num_rows());
+        assert_eq!(arrays, batch.columns());
+
+        let err = match reader.read_batch(&batch.schema()).unwrap_err() {
+            DataFusionError::IoError(err) => err,
+            err => panic!("unexpected error: {err}"),
+        };
+        let checksum_err = err
+            .get_ref()
+            .and_then(|err| err.downcast_ref::<IpcChecksumError>())
+            .expect("expect IpcChecksumError");
+        assert_eq!(
+            checksum_err.block_offset,
+            (base_offset + second_block_offset) as u64
+        );
+        assert_eq!(checksum_err.block_index, 1);
+        assert_ne!(checksum_err.expected, checksum_err.actual);
+        Ok(())
+    }
 }
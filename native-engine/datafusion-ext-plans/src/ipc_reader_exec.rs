@@ -188,7 +188,7 @@ fn read_ipc(
             .or_else(|err| df_execution_err!("{err}"))??;
 
             // get ipc reader
-            let mut reader = Box::pin(match next {
+            let mut reader = match next {
                 Some(block) if jni_call!(BlazeBlockObject(block.as_obj()).hasFileSegment() -> bool)? => {
                     get_file_reader(block.as_obj())?
                 }
@@ -197,9 +197,25 @@ fn read_ipc(
                 }
                 Some(block) => get_channel_reader(block.as_obj())?,
                 None => break,
-            });
-
-            while let Some((num_rows, cols)) = reader.as_mut().read_batch(&schema)? {
+            };
+
+            // reading from file segments and channels may block on io, so read
+            // batches in blocking threads instead of the tokio runtime threads.
+            // the reader is moved into the blocking task and returned with the
+            // result, so it is never shared with a task that is still reading
+            loop {
+                let schema = schema.clone();
+                let (returned_reader, next_batch) = tokio::task::spawn_blocking(move || {
+                    let next_batch = reader.read_batch(&schema);
+                    (reader, next_batch)
+                })
+                .await
+                .or_else(|err| df_execution_err!("{err}"))?;
+                reader = returned_reader;
+
+                let Some((num_rows, cols)) = next_batch? else {
+                    break;
+                };
                 let (cur_staging_num_rows, cur_staging_mem_size) = {
                     let staging_cols_cloned = staging_cols.clone();
                     let mut staging_cols = staging_cols_cloned.lock();
@@ -272,9 +288,10 @@ fn get_file_reader(block: JObject) -> Result<IpcCompressionReader<Box<dyn Read +
     let mut file = File::open(&path)?;
     file.seek(SeekFrom::Start(offset as u64))?;
 
-    Ok(IpcCompressionReader::new(Box::new(
-        BufReader::with_capacity(65536, file.take(length as u64)),
-    )))
+    Ok(IpcCompressionReader::new_with_base_offset(
+        Box::new(BufReader::with_capacity(65536, file.take(length as u64))),
+        offset as u64,
+    ))
 }
 
 fn get_byte_buffer_reader(block: JObject) -> Result<IpcCompressionReader<Box<dyn Read + Send>>> {
@@ -156,6 +156,8 @@ pub async fn write_ipc(
                 }
             }
 
+            // frame splitting is not enabled here, broadcast hash map batches
+            // must be read back as exactly one batch
             let mut writer = IpcCompressionWriter::new(IpcConsumerWrite(ipc_consumer));
             while let Some(batch) = exec_ctx
                 .baseline_metrics()
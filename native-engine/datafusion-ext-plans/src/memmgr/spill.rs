@@ -14,6 +14,7 @@
 
 use std::{
     any::Any,
+    fmt::{Display, Formatter},
     fs::{File, OpenOptions},
     io::{BufReader, BufWriter, Cursor, Read, Seek, Write},
     sync::Arc,
@@ -24,15 +25,22 @@ use blaze_jni_bridge::{
     is_jni_bridge_inited, jni_bridge::LocalRef, jni_call, jni_call_static, jni_get_string,
     jni_new_direct_byte_buffer, jni_new_global_ref,
 };
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 use datafusion::{common::Result, parquet::file::reader::Length, physical_plan::metrics::Time};
 use jni::{objects::GlobalRef, sys::jlong};
 
-use crate::memmgr::metrics::SpillMetrics;
+use crate::{common::ipc_compression::io_checksum_enabled, memmgr::metrics::SpillMetrics};
+
+// spills are lz4 frames written over a sequence of blocks. every block starts
+// with a u32 block length. if the highest bit is set, the length is followed by
+// a u32 crc32c (castagnoli) checksum of the block data.
+const SPILL_BLOCK_SIZE: usize = 65536;
+const SPILL_BLOCK_CHECKSUM_FLAG: u32 = 1 << 31;
 
 pub type SpillCompressedReader<'a> =
-    lz4_flex::frame::FrameDecoder<BufReader<Box<dyn Read + Send + 'a>>>;
+    lz4_flex::frame::FrameDecoder<SpillBlockReader<BufReader<Box<dyn Read + Send + 'a>>>>;
 pub type SpillCompressedWriter<'a> =
-    lz4_flex::frame::AutoFinishEncoder<BufWriter<Box<dyn Write + Send + 'a>>>;
+    lz4_flex::frame::AutoFinishEncoder<SpillBlockWriter<BufWriter<Box<dyn Write + Send + 'a>>>>;
 
 pub trait Spill: Send + Sync {
     fn as_any(&self) -> &dyn Any;
@@ -41,11 +49,164 @@ pub trait Spill: Send + Sync {
     fn get_buf_writer<'a>(&'a mut self) -> BufWriter<Box<dyn Write + Send + 'a>>;
 
     fn get_compressed_reader(&self) -> SpillCompressedReader<'_> {
-        lz4_flex::frame::FrameDecoder::new(self.get_buf_reader())
+        lz4_flex::frame::FrameDecoder::new(SpillBlockReader::new(self.get_buf_reader()))
     }
 
     fn get_compressed_writer(&mut self) -> SpillCompressedWriter<'_> {
-        lz4_flex::frame::FrameEncoder::new(self.get_buf_writer()).auto_finish()
+        self.get_compressed_writer_with_checksum(io_checksum_enabled())
+    }
+
+    fn get_compressed_writer_with_checksum(&mut self, checksum: bool) -> SpillCompressedWriter<'_> {
+        let block_writer = SpillBlockWriter::new(self.get_buf_writer(), checksum);
+        lz4_flex::frame::FrameEncoder::new(block_writer).auto_finish()
+    }
+}
+
+/// Error returned by [`SpillBlockReader`] when the checksum of a spilled block
+/// does not match its data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpillChecksumError {
+    pub block_offset: u64,
+    pub block_index: usize,
+    pub expected: u32,
+    pub actual: u32,
+}
+
+impl Display for SpillChecksumError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "spill block checksum mismatch (block_offset={}, block_index={}): \
+             expected {:08x}, actual {:08x}",
+            self.block_offset, self.block_index, self.expected, self.actual,
+        )
+    }
+}
+
+impl std::error::Error for SpillChecksumError {}
+
+pub struct SpillBlockWriter<W: Write> {
+    inner: W,
+    buf: Vec<u8>,
+    checksum: bool,
+}
+
+impl<W: Write> SpillBlockWriter<W> {
+    fn new(inner: W, checksum: bool) -> Self {
+        Self {
+            inner,
+            buf: Vec::with_capacity(SPILL_BLOCK_SIZE),
+            checksum,
+        }
+    }
+
+    fn write_block(&mut self) -> std::io::Result<()> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        if self.checksum {
+            let header = self.buf.len() as u32 | SPILL_BLOCK_CHECKSUM_FLAG;
+            self.inner.write_u32::<LittleEndian>(header)?;
+            self.inner
+                .write_u32::<LittleEndian>(crc32c::crc32c(&self.buf))?;
+        } else {
+            self.inner
+                .write_u32::<LittleEndian>(self.buf.len() as u32)?;
+        }
+        self.inner.write_all(&self.buf)?;
+        self.buf.clear();
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for SpillBlockWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let len = buf.len().min(SPILL_BLOCK_SIZE - self.buf.len());
+        self.buf.extend_from_slice(&buf[..len]);
+        if self.buf.len() == SPILL_BLOCK_SIZE {
+            self.write_block()?;
+        }
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.write_block()?;
+        self.inner.flush()
+    }
+}
+
+impl<W: Write> Drop for SpillBlockWriter<W> {
+    fn drop(&mut self) {
+        let _ = self.write_block();
+    }
+}
+
+pub struct SpillBlockReader<R: Read> {
+    inner: R,
+    block: Vec<u8>,
+    pos: usize,
+    block_offset: u64,
+    block_index: usize,
+}
+
+impl<R: Read> SpillBlockReader<R> {
+    fn new(inner: R) -> Self {
+        Self {
+            inner,
+            block: vec![],
+            pos: 0,
+            block_offset: 0,
+            block_index: 0,
+        }
+    }
+
+    fn read_block(&mut self) -> std::io::Result<bool> {
+        let header = match self.inner.read_u32::<LittleEndian>() {
+            Ok(header) => header,
+            Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(false),
+            Err(err) => return Err(err),
+        };
+        let (block_len, checksum, header_len) = if header & SPILL_BLOCK_CHECKSUM_FLAG != 0 {
+            let checksum = self.inner.read_u32::<LittleEndian>()?;
+            (header & !SPILL_BLOCK_CHECKSUM_FLAG, Some(checksum), 8)
+        } else {
+            (header, None, 4)
+        };
+
+        self.block.resize(block_len as usize, 0);
+        self.inner.read_exact(&mut self.block)?;
+        if let Some(expected) = checksum {
+            let actual = crc32c::crc32c(&self.block);
+            if actual != expected {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    SpillChecksumError {
+                        block_offset: self.block_offset,
+                        block_index: self.block_index,
+                        expected,
+                        actual,
+                    },
+                ));
+            }
+        }
+        self.pos = 0;
+        self.block_offset += header_len + block_len as u64;
+        self.block_index += 1;
+        Ok(true)
+    }
+}
+
+impl<R: Read> Read for SpillBlockReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.pos == self.block.len() {
+            if !self.read_block()? {
+                return Ok(0);
+            }
+        }
+        let len = buf.len().min(self.block.len() - self.pos);
+        buf[..len].copy_from_slice(&self.block[self.pos..][..len]);
+        self.pos += len;
+        Ok(len)
     }
 }
 
@@ -271,3 +432,53 @@ impl<W: Write> Write for IoTimeWriteWrapper<W> {
         self.0.flush()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_spill_checksum_mismatch() -> Result<()> {
+        // poorly compressible data, so that the spill has several blocks
+        let data = (0..100000u64)
+            .flat_map(|i| (i.wrapping_mul(0x9e3779b97f4a7c15) >> 32).to_le_bytes())
+            .collect::<Vec<_>>();
+        let mut spill: Box<dyn Spill> = Box::new(vec![]);
+        for checksum in [false, true] {
+            spill
+                .as_any_mut()
+                .downcast_mut::<Vec<u8>>()
+                .unwrap()
+                .clear();
+            {
+                let mut writer = spill.get_compressed_writer_with_checksum(checksum);
+                writer.write_all(&data)?;
+            }
+            let mut decompressed = vec![];
+            spill
+                .get_compressed_reader()
+                .read_to_end(&mut decompressed)?;
+            assert_eq!(decompressed, data);
+        }
+
+        // flip a byte in the middle of the second block
+        let spilled = spill.as_any_mut().downcast_mut::<Vec<u8>>().unwrap();
+        assert!(spilled.len() > 2 * (SPILL_BLOCK_SIZE + 8));
+        let second_block_offset = SPILL_BLOCK_SIZE + 8;
+        spilled[second_block_offset + SPILL_BLOCK_SIZE / 2] ^= 0xff;
+
+        let err = spill
+            .get_compressed_reader()
+            .read_to_end(&mut vec![])
+            .expect_err("corrupted spill must not be read");
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        let checksum_err = err
+            .get_ref()
+            .and_then(|err| err.downcast_ref::<SpillChecksumError>())
+            .expect("expect SpillChecksumError");
+        assert_eq!(checksum_err.block_offset, second_block_offset as u64);
+        assert_eq!(checksum_err.block_index, 1);
+        assert_ne!(checksum_err.expected, checksum_err.actual);
+        Ok(())
+    }
+}
@@ -119,10 +119,12 @@ impl ExecutionPlan for RssShuffleWriterExec {
         let rss_partition_writer = jni_new_global_ref!(rss_partition_writer_local.as_obj())?;
 
         let input = exec_ctx.execute(&self.input)?;
+        let oversized_frame_count = exec_ctx.register_counter_metric("oversized_frame_count");
         let repartitioner: Arc<dyn ShuffleRepartitioner> = match &self.partitioning {
-            p if p.partition_count() == 1 => {
-                Arc::new(RssSingleShuffleRepartitioner::new(rss_partition_writer))
-            }
+            p if p.partition_count() == 1 => Arc::new(RssSingleShuffleRepartitioner::new(
+                rss_partition_writer,
+                oversized_frame_count,
+            )),
             Partitioning::Hash(..) => {
                 let sort_time = exec_ctx.register_timer_metric("sort_time");
                 let partitioner = Arc::new(RssSortShuffleRepartitioner::new(
@@ -130,6 +132,7 @@ impl ExecutionPlan for RssShuffleWriterExec {
                     rss_partition_writer,
                     self.partitioning.clone(),
                     sort_time,
+                    oversized_frame_count,
                 ));
                 MemManager::register_consumer(partitioner.clone(), true);
                 partitioner
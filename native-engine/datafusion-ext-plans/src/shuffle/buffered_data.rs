@@ -19,7 +19,10 @@ use blaze_jni_bridge::jni_call;
 use count_write::CountWrite;
 use datafusion::{
     common::Result,
-    physical_plan::{metrics::Time, Partitioning},
+    physical_plan::{
+        metrics::{Count, Time},
+        Partitioning,
+    },
 };
 use datafusion_ext_commons::{
     array_size::ArraySize,
@@ -48,10 +51,11 @@ pub struct BufferedData {
     staging_mem_used: usize,
     sorted_mem_used: usize,
     sort_time: Time,
+    oversized_frame_count: Count,
 }
 
 impl BufferedData {
-    pub fn new(partition_id: usize, sort_time: Time) -> Self {
+    pub fn new(partition_id: usize, sort_time: Time, oversized_frame_count: Count) -> Self {
         Self {
             partition_id,
             sorted_batches: vec![],
@@ -60,11 +64,19 @@ impl BufferedData {
             staging_mem_used: 0,
             sorted_mem_used: 0,
             sort_time,
+            oversized_frame_count,
         }
     }
 
     pub fn drain(&mut self) -> Self {
-        std::mem::replace(self, Self::new(self.partition_id, self.sort_time.clone()))
+        std::mem::replace(
+            self,
+            Self::new(
+                self.partition_id,
+                self.sort_time.clone(),
+                self.oversized_frame_count.clone(),
+            ),
+        )
     }
 
     pub fn add_batch(&mut self, batch: RecordBatch, partitioning: &Partitioning) -> Result<()> {
@@ -93,6 +105,7 @@ impl BufferedData {
             return Ok(vec![0; partitioning.partition_count() + 1]);
         }
         let mut writer = IpcCompressionWriter::new(CountWrite::from(&mut w));
+        writer.enable_frame_splitting(self.oversized_frame_count.clone());
         let mut offsets = vec![];
         let mut offset = 0;
         let mut iter = self.into_sorted_batches(partitioning)?;
@@ -136,8 +149,10 @@ impl BufferedData {
         if self.num_rows == 0 {
             return Ok(());
         }
+        let oversized_frame_count = self.oversized_frame_count.clone();
         let mut iter = self.into_sorted_batches(partitioning)?;
         let mut writer = IpcCompressionWriter::new(RssWriter::new(rss_partition_writer.clone(), 0));
+        writer.enable_frame_splitting(oversized_frame_count);
 
         while (iter.cur_part_id() as usize) < partitioning.partition_count() {
             let cur_part_id = iter.cur_part_id();
@@ -15,7 +15,7 @@
 use std::sync::Arc;
 
 use async_trait::async_trait;
-use datafusion::{arrow::record_batch::RecordBatch, common::Result};
+use datafusion::{arrow::record_batch::RecordBatch, common::Result, physical_plan::metrics::Count};
 use datafusion_ext_commons::df_execution_err;
 use jni::objects::GlobalRef;
 use parking_lot::Mutex;
@@ -30,12 +30,11 @@ pub struct RssSingleShuffleRepartitioner {
 }
 
 impl RssSingleShuffleRepartitioner {
-    pub fn new(rss_partition_writer: GlobalRef) -> Self {
+    pub fn new(rss_partition_writer: GlobalRef, oversized_frame_count: Count) -> Self {
+        let mut writer = IpcCompressionWriter::new(RssWriter::new(rss_partition_writer, 0));
+        writer.enable_frame_splitting(oversized_frame_count);
         Self {
-            rss_partition_writer: Arc::new(Mutex::new(IpcCompressionWriter::new(RssWriter::new(
-                rss_partition_writer,
-                0,
-            )))),
+            rss_partition_writer: Arc::new(Mutex::new(writer)),
         }
     }
 }
@@ -18,7 +18,10 @@ use arrow::record_batch::RecordBatch;
 use async_trait::async_trait;
 use datafusion::{
     common::Result,
-    physical_plan::{metrics::Time, Partitioning},
+    physical_plan::{
+        metrics::{Count, Time},
+        Partitioning,
+    },
 };
 use datafusion_ext_commons::{array_size::ArraySize, df_execution_err};
 use futures::lock::Mutex;
@@ -43,11 +46,16 @@ impl RssSortShuffleRepartitioner {
         rss_partition_writer: GlobalRef,
         partitioning: Partitioning,
         sort_time: Time,
+        oversized_frame_count: Count,
     ) -> Self {
         Self {
             name: format!("RssSortShufflePartitioner[partition={}]", partition_id),
             mem_consumer_info: None,
-            data: Mutex::new(BufferedData::new(partition_id, sort_time)),
+            data: Mutex::new(BufferedData::new(
+                partition_id,
+                sort_time,
+                oversized_frame_count,
+            )),
             partitioning,
             rss: rss_partition_writer,
         }
@@ -20,7 +20,10 @@ use std::{
 
 use arrow::record_batch::RecordBatch;
 use async_trait::async_trait;
-use datafusion::{common::Result, physical_plan::metrics::Time};
+use datafusion::{
+    common::Result,
+    physical_plan::metrics::{Count, Time},
+};
 use tokio::sync::Mutex;
 
 use crate::{
@@ -36,15 +39,22 @@ pub struct SingleShuffleRepartitioner {
     output_index_file: String,
     output_data: Arc<Mutex<Option<IpcCompressionWriter<TimedWriter<File>>>>>,
     output_io_time: Time,
+    oversized_frame_count: Count,
 }
 
 impl SingleShuffleRepartitioner {
-    pub fn new(output_data_file: String, output_index_file: String, output_io_time: Time) -> Self {
+    pub fn new(
+        output_data_file: String,
+        output_index_file: String,
+        output_io_time: Time,
+        oversized_frame_count: Count,
+    ) -> Self {
         Self {
             output_data_file,
             output_index_file,
             output_data: Arc::new(Mutex::default()),
             output_io_time,
+            oversized_frame_count,
         }
     }
 
@@ -53,7 +63,7 @@ impl SingleShuffleRepartitioner {
         output_data: &'a mut Option<IpcCompressionWriter<TimedWriter<File>>>,
     ) -> Result<&'a mut IpcCompressionWriter<TimedWriter<File>>> {
         if output_data.is_none() {
-            *output_data = Some(IpcCompressionWriter::new(
+            let mut writer = IpcCompressionWriter::new(
                 self.output_io_time.wrap_writer(
                     OpenOptions::new()
                         .write(true)
@@ -61,7 +71,9 @@ impl SingleShuffleRepartitioner {
                         .truncate(true)
                         .open(&self.output_data_file)?,
                 ),
-            ));
+            );
+            writer.enable_frame_splitting(self.oversized_frame_count.clone());
+            *output_data = Some(writer);
         }
         Ok(output_data.as_mut().unwrap())
     }
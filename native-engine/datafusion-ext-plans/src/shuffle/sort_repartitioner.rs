@@ -64,6 +64,7 @@ impl SortShuffleRepartitioner {
     ) -> Self {
         let partition_id = exec_ctx.partition_id();
         let sort_time = exec_ctx.register_timer_metric("sort_time");
+        let oversized_frame_count = exec_ctx.register_counter_metric("oversized_frame_count");
         let num_output_partitions = partitioning.partition_count();
         Self {
             exec_ctx,
@@ -71,7 +72,11 @@ impl SortShuffleRepartitioner {
             mem_consumer_info: None,
             output_data_file,
             output_index_file,
-            data: Mutex::new(BufferedData::new(partition_id, sort_time)),
+            data: Mutex::new(BufferedData::new(
+                partition_id,
+                sort_time,
+                oversized_frame_count,
+            )),
             spills: Mutex::default(),
             partitioning,
             num_output_partitions,
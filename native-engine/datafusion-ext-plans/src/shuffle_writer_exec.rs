@@ -116,6 +116,7 @@ impl ExecutionPlan for ShuffleWriterExec {
                 self.output_data_file.clone(),
                 self.output_index_file.clone(),
                 output_time,
+                exec_ctx.register_counter_metric("oversized_frame_count"),
             )),
             Partitioning::Hash(..) => {
                 let partitioner = Arc::new(SortShuffleRepartitioner::new(
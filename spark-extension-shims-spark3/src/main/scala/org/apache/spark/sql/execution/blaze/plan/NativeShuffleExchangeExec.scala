@@ -61,6 +61,7 @@ case class NativeShuffleExchangeExec(
           "disk_spill_iotime",
           "sort_time",
           "output_io_time",
+          "oversized_frame_count",
           "shuffle_read_total_time"))
         .toSeq: _*)).toMap
 
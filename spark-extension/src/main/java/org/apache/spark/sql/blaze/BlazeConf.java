@@ -72,6 +72,20 @@ public enum BlazeConf {
     // spark io compression codec
     SPARK_IO_COMPRESSION_CODEC("spark.io.compression.codec", "lz4"),
 
+    // compression codec of native shuffle data (none/lz4/zstd)
+    // uses spark.io.compression.codec if not set
+    SHUFFLE_COMPRESSION_CODEC("spark.blaze.shuffle.compression.codec", ""),
+
+    // zstd compression level of native shuffle data
+    SHUFFLE_COMPRESSION_ZSTD_LEVEL("spark.blaze.shuffle.compression.zstd.level", 1),
+
+    // write checksums into native shuffle/spill data and verify them while reading
+    IO_CHECKSUM_ENABLE("spark.blaze.io.checksum.enable", false),
+
+    // max size of a serialized batch frame in native shuffle data, bigger batches are split.
+    // must be positive
+    IO_MAX_FRAME_SIZE("spark.blaze.io.maxFrameSize", 268435456),
+
     // replace all sort-merge join to shuffled-hash join, only used for benchmarking
     FORCE_SHUFFLED_HASH_JOIN("spark.blaze.forceShuffledHashJoin", false);
 
@@ -111,6 +111,7 @@ object NativeHelper extends Logging {
       "disk_spill_iotime" -> nanoTimingMetric("Native.disk_spill_iotime"),
       "sort_time" -> nanoTimingMetric("Native.sort_time"),
       "output_io_time" -> nanoTimingMetric("Native.output_io_time"),
+      "oversized_frame_count" -> metric("Native.oversized_frame_count"),
       "shuffle_read_total_time" -> nanoTimingMetric("Native.shuffle_read_total_time"))
 
     if (BlazeConf.INPUT_BATCH_STATISTICS_ENABLE.booleanConf()) {
@@ -0,0 +1,120 @@
+/*
+ * Copyright 2022 The Blaze Authors
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+package org.apache.spark.sql.blaze
+
+import java.io.ByteArrayOutputStream
+import java.nio.ByteBuffer
+import java.nio.ByteOrder
+import java.nio.file.Files
+import java.nio.file.Path
+import java.nio.file.Paths
+
+import org.scalatest.funsuite.AnyFunSuite
+
+import org.apache.spark.sql.catalyst.InternalRow
+import org.apache.spark.sql.catalyst.expressions.UnsafeProjection
+import org.apache.spark.sql.types._
+import org.apache.spark.unsafe.types.UTF8String
+
+/**
+ * Checks the UnsafeRow fixtures used by the native spark_unsafe_row tests against the
+ * bytes produced by spark's UnsafeProjection. Each fixture is a sequence of rows, each
+ * prefixed by its length as a little-endian u32.
+ *
+ * The build does not run scala suites, so run it with scalatest's runner from the
+ * spark-extension directory, after `mvn test-compile`:
+ *
+ *   java -cp <test classpath> org.scalatest.tools.Runner -R target/test-classes \
+ *     -s org.apache.spark.sql.blaze.UnsafeRowFixturesSuite
+ *
+ * Add -Dblaze.unsafeRowFixtures.regenerate=true to rewrite the fixtures.
+ */
+class UnsafeRowFixturesSuite extends AnyFunSuite {
+  private val fixtureDir: Path =
+    Paths.get("..", "native-engine", "datafusion-ext-commons", "testdata", "unsafe_row")
+  private val regenerate: Boolean =
+    sys.props.get("blaze.unsafeRowFixtures.regenerate").contains("true")
+
+  test("golden_rows.bin") {
+    val schema = StructType(
+      Seq(
+        StructField("id", IntegerType),
+        StructField("name", StringType),
+        StructField("amount", DecimalType(10, 2)),
+        StructField("big", DecimalType(24, 2)),
+        StructField("flag", BooleanType)))
+    checkFixture(
+      "golden_rows.bin",
+      schema,
+      Seq(
+        InternalRow(1, UTF8String.fromString("hello"), decimal("123.45", 10), null, true),
+        InternalRow(
+          null,
+          UTF8String.fromString(""),
+          decimal("-1.00", 10),
+          decimal("12345678901234567890.12", 24),
+          false),
+        InternalRow(-7, UTF8String.fromString("你好"), null, decimal("-0.01", 24), null)))
+  }
+
+  test("primitives.bin") {
+    val schema = StructType(
+      Seq(
+        StructField("i8", ByteType),
+        StructField("i16", ShortType),
+        StructField("i64", LongType),
+        StructField("f32", FloatType),
+        StructField("f64", DoubleType),
+        StructField("date", DateType),
+        StructField("ts", TimestampType),
+        StructField("bin", BinaryType)))
+    checkFixture(
+      "primitives.bin",
+      schema,
+      Seq(
+        InternalRow(
+          -2.toByte,
+          300.toShort,
+          -1L,
+          1.5f,
+          -0.25d,
+          -1,
+          null,
+          Array[Byte](1, 2, 3))))
+  }
+
+  private def decimal(value: String, precision: Int): Decimal =
+    Decimal(BigDecimal(value), precision, 2)
+
+  private def checkFixture(name: String, schema: StructType, rows: Seq[InternalRow]): Unit = {
+    val projection = UnsafeProjection.create(schema)
+    val output = new ByteArrayOutputStream()
+    rows.foreach { row =>
+      val bytes = projection(row).getBytes
+      val len = ByteBuffer.allocate(4).order(ByteOrder.LITTLE_ENDIAN).putInt(bytes.length)
+      output.write(len.array())
+      output.write(bytes)
+    }
+
+    val path = fixtureDir.resolve(name)
+    if (regenerate) {
+      Files.write(path, output.toByteArray)
+    }
+    assert(
+      Files.readAllBytes(path).sameElements(output.toByteArray),
+      s"$path differs from spark's UnsafeProjection output")
+  }
+}